@@ -0,0 +1,211 @@
+use crate::items::{menu_lines, Catalog};
+use crate::sk::SkDatum;
+use std::collections::{BTreeMap, HashMap};
+
+/// item prices per month ("YYYY/MM") learned from prepaid records.
+///
+/// single-item purchases give prices directly. they are propagated to combinations
+/// where only one item is unknown, first within the month and then using prices of
+/// earlier months for the rest.
+pub fn learn_prices(records: &[SkDatum]) -> BTreeMap<String, BTreeMap<String, u32>> {
+    let mut months = BTreeMap::<String, Vec<&SkDatum>>::new();
+    for it in records {
+        months
+            .entry(it.date.format("%Y/%m").to_string())
+            .or_default()
+            .push(it);
+    }
+
+    let mut history = BTreeMap::<String, BTreeMap<String, u32>>::new();
+    let mut latest = HashMap::<String, u32>::new();
+    for (month, mut records) in months {
+        records.sort_by_key(|it| it.date);
+
+        // later purchases win when the price changed within the month.
+        let mut known = HashMap::<String, u32>::new();
+        for it in &records {
+            let lines = menu_lines(&it.menu);
+            if lines.len() == 1 {
+                known.insert(lines[0].clone(), it.price);
+            }
+        }
+
+        let empty = HashMap::new();
+        for fallback in [&empty, &latest] {
+            loop {
+                let mut progress = false;
+                for it in &records {
+                    let lines = menu_lines(&it.menu);
+                    if let Some((item, price)) = solve(&lines, it.price, &known, fallback) {
+                        known.insert(item, price);
+                        progress = true;
+                    }
+                }
+                if !progress {
+                    break;
+                }
+            }
+        }
+
+        for (item, price) in known {
+            history
+                .entry(item.clone())
+                .or_default()
+                .insert(month.clone(), price);
+            latest.insert(item, price);
+        }
+    }
+    return history;
+}
+
+/// price of the only unknown item of the combination. it may appear several times.
+fn solve(
+    lines: &[String],
+    total: u32,
+    known: &HashMap<String, u32>,
+    fallback: &HashMap<String, u32>,
+) -> Option<(String, u32)> {
+    if lines.len() <= 1 {
+        return None;
+    }
+
+    let mut rest = total as i64;
+    let mut unknown: Option<&String> = None;
+    let mut count = 0;
+    for line in lines {
+        if let Some(price) = known.get(line).or_else(|| fallback.get(line)) {
+            rest -= *price as i64;
+        } else if unknown.map_or(true, |it| it == line) {
+            unknown = Some(line);
+            count += 1;
+        } else {
+            return None;
+        }
+    }
+
+    let unknown = unknown?;
+    if rest <= 0 || rest % count != 0 {
+        return None;
+    }
+    return Some((unknown.clone(), (rest / count) as u32));
+}
+
+/// merges learned prices into the catalog. the latest month becomes the current price.
+/// prices written by hand, which differ from the latest learned price, are kept.
+pub fn merge_history(catalog: &mut Catalog, history: BTreeMap<String, BTreeMap<String, u32>>) {
+    for (item, months) in history {
+        let entry = catalog.history.entry(item.clone()).or_default();
+        let learned = entry.values().next_back().copied();
+        let current = catalog.prices.get(&item).copied();
+        entry.extend(months);
+        if current.is_some() && current != learned {
+            continue;
+        }
+        if let Some(price) = entry.values().next_back() {
+            catalog.prices.insert(item, *price);
+        }
+    }
+}
+
+/// current prices with the months the price changed.
+pub fn format_catalog(catalog: &Catalog) -> String {
+    let mut text = String::new();
+    for (item, price) in &catalog.prices {
+        text.push_str(&format!("{} {}円", item, price));
+
+        let mut changes = Vec::new();
+        let mut last = None;
+        for (month, price) in catalog.history.get(item).into_iter().flatten() {
+            if last != Some(price) {
+                changes.push(format!("{} {}円", month, price));
+                last = Some(price);
+            }
+        }
+        if changes.len() > 1 {
+            text.push_str(&format!(" ({})", changes.join(" -> ")));
+        }
+        text.push('\n');
+    }
+    return text;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sk::make_datum;
+    use chrono::TimeZone;
+    use chrono_tz::Asia::Tokyo;
+
+    #[test]
+    fn learn_prices_test() {
+        let records = vec![
+            make_datum(Tokyo.ymd(2022, 4, 1), "shop", "チキンカツカレーM", 407),
+            make_datum(
+                Tokyo.ymd(2022, 4, 2),
+                "shop",
+                "チキンカツカレーM/ほうれん草",
+                473,
+            ),
+            make_datum(
+                Tokyo.ymd(2022, 4, 3),
+                "shop",
+                "ほうれん草/鶏きも煮/チキンカツカレーM",
+                561,
+            ),
+            make_datum(Tokyo.ymd(2022, 4, 4), "shop", "ライスS/ライスS/味噌汁", 200), // two unknown items
+            make_datum(Tokyo.ymd(2022, 10, 1), "shop", "チキンカツカレーM", 407),
+            make_datum(
+                Tokyo.ymd(2022, 10, 1),
+                "shop",
+                "チキンカツカレーM/ほうれん草",
+                506,
+            ),
+            make_datum(Tokyo.ymd(2022, 10, 2), "shop", "味噌汁", 33),
+            make_datum(
+                Tokyo.ymd(2022, 10, 3),
+                "shop",
+                "ライスS/ライスS/味噌汁",
+                209,
+            ),
+            make_datum(Tokyo.ymd(2022, 10, 4), "shop", "鶏きも煮/冷奴", 143), // 鶏きも煮 from the earlier month
+        ];
+        let history = learn_prices(&records);
+
+        assert_eq!(history["チキンカツカレーM"]["2022/04"], 407);
+        assert_eq!(history["チキンカツカレーM"]["2022/10"], 407);
+        assert_eq!(history["ほうれん草"]["2022/04"], 66);
+        assert_eq!(history["ほうれん草"]["2022/10"], 99);
+        assert_eq!(history["鶏きも煮"]["2022/04"], 88);
+        assert_eq!(history["ライスS"]["2022/10"], 88);
+        assert!(!history["ライスS"].contains_key("2022/04"));
+        assert_eq!(history["冷奴"]["2022/10"], 55);
+
+        let mut catalog = Catalog::default();
+        catalog.prices.insert("ほうじ茶".to_string(), 0);
+        // written by hand for the item learned as 55
+        catalog.prices.insert("冷奴".to_string(), 60);
+        merge_history(&mut catalog, history);
+        assert_eq!(catalog.prices["ほうれん草"], 99);
+        assert_eq!(catalog.prices["ほうじ茶"], 0);
+        assert_eq!(catalog.prices["冷奴"], 60);
+        assert_eq!(catalog.history["冷奴"]["2022/10"], 55);
+
+        let text = format_catalog(&catalog);
+        assert!(text.contains("ほうれん草 99円 (2022/04 66円 -> 2022/10 99円)\n"));
+        assert!(text.contains("チキンカツカレーM 407円\n"));
+        assert!(text.contains("鶏きも煮 88円\n"));
+        assert!(text.contains("ほうじ茶 0円\n"));
+
+        // learned prices follow the later months
+        let mut history = BTreeMap::new();
+        for item in ["ほうれん草", "冷奴"] {
+            history.insert(
+                item.to_string(),
+                BTreeMap::from([("2023/04".to_string(), 110)]),
+            );
+        }
+        merge_history(&mut catalog, history);
+        assert_eq!(catalog.prices["ほうれん草"], 110);
+        assert_eq!(catalog.prices["冷奴"], 60);
+    }
+}
@@ -0,0 +1,260 @@
+use crate::config::{BalanceConfig, SkMfConfig};
+use crate::mf::MfSession;
+use crate::shops::ShopTable;
+use std::collections::BTreeMap;
+
+/// names in Moneyforward which the config refers to.
+pub trait MfNames {
+    /// accounts whose history is fetched.
+    fn has_account(&self, name: &str) -> bool;
+    /// subaccounts which entries are registered to or transferred from.
+    fn has_subaccount(&self, name: &str) -> bool;
+    /// a middle category under the large category.
+    fn has_category(&self, large: &str, middle: &str) -> bool;
+}
+
+impl MfNames for MfSession {
+    fn has_account(&self, name: &str) -> bool {
+        return self.accounts().contains_key(name);
+    }
+
+    fn has_subaccount(&self, name: &str) -> bool {
+        return self.subaccounts().contains_key(name);
+    }
+
+    fn has_category(&self, large: &str, middle: &str) -> bool {
+        return self
+            .categories()
+            .get(large)
+            .map_or(false, |it| it.subcategory.contains_key(middle));
+    }
+}
+
+/// names written in a file instead of a live session.
+#[derive(serde_derive::Deserialize, Debug, Default)]
+pub struct MockNames {
+    #[serde(default)]
+    pub accounts: Vec<String>,
+    #[serde(default)]
+    pub subaccounts: Vec<String>,
+    /// large category to its middle categories
+    #[serde(default)]
+    pub categories: BTreeMap<String, Vec<String>>,
+}
+
+impl MfNames for MockNames {
+    fn has_account(&self, name: &str) -> bool {
+        return self.accounts.iter().any(|it| it == name);
+    }
+
+    fn has_subaccount(&self, name: &str) -> bool {
+        return self.subaccounts.iter().any(|it| it == name);
+    }
+
+    fn has_category(&self, large: &str, middle: &str) -> bool {
+        return self
+            .categories
+            .get(large)
+            .map_or(false, |it| it.iter().any(|m| m == middle));
+    }
+}
+
+/// reads names of the mock session from a toml file.
+pub fn load_mock_names(path: &str) -> Result<MockNames, String> {
+    let data =
+        std::fs::read_to_string(path).map_err(|e| format!("names[{}] load err:{}", path, e))?;
+    return toml::from_str(&data).map_err(|e| format!("names[{}] parse err:{}", path, e));
+}
+
+/// returns problems of the names in the config. empty when every record can be sent.
+pub fn check_config(
+    skmf: &SkMfConfig,
+    shops: &ShopTable,
+    balance: Option<&BalanceConfig>,
+    names: &dyn MfNames,
+) -> Vec<String> {
+    let mut problems = Vec::new();
+    let mut subaccount = |key: &str, name: &str| {
+        if !names.has_subaccount(name) {
+            problems.push(format!("skmf.{} [{}] not found in subaccounts", key, name));
+        }
+    };
+    subaccount("mf_subaccount", &skmf.mf_subaccount);
+    subaccount("mf_subaccount_from", &skmf.mf_subaccount_from);
+    if let Some(from) = &skmf.mf_account_subaccount_from {
+        subaccount("mf_account_subaccount_from", from);
+    }
+    if !names.has_account(&skmf.mf_subaccount) {
+        problems.push(format!(
+            "skmf.mf_subaccount [{}] not found in accounts",
+            skmf.mf_subaccount
+        ));
+    }
+
+    for (key, large, middle) in category_pairs(skmf, shops, balance) {
+        if !names.has_category(large, middle) {
+            problems.push(format!("{} [{}/{}] not found", key, large, middle));
+        }
+    }
+    if let Some(balance) = balance.filter(|it| it.adjust) {
+        if balance.large_category.is_none() || balance.middle_category.is_none() {
+            problems.push(
+                "balance.large_category and middle_category are required to adjust".to_string(),
+            );
+        }
+    }
+
+    return problems;
+}
+
+/// pairs of large and middle categories which records are sent with, keyed by the config key.
+fn category_pairs<'a>(
+    skmf: &'a SkMfConfig,
+    shops: &'a ShopTable,
+    balance: Option<&'a BalanceConfig>,
+) -> Vec<(String, &'a str, &'a str)> {
+    let mut pairs = vec![
+        (
+            "skmf.mf_large_category/mf_middle_category".to_string(),
+            skmf.mf_large_category.as_str(),
+            skmf.mf_middle_category.as_str(),
+        ),
+        (
+            "skmf.mf_charge_large_category/mf_charge_middle_category".to_string(),
+            skmf.mf_charge_large_category.as_str(),
+            skmf.mf_charge_middle_category.as_str(),
+        ),
+    ];
+
+    let mut raws: Vec<&String> = shops.keys().collect();
+    raws.sort();
+    for raw in raws {
+        let shop = &shops[raw];
+        if shop.large_category.is_some() || shop.middle_category.is_some() {
+            pairs.push((
+                format!("shops.\"{}\"", raw),
+                shop.large_category
+                    .as_deref()
+                    .unwrap_or(&skmf.mf_large_category),
+                shop.middle_category
+                    .as_deref()
+                    .unwrap_or(&skmf.mf_middle_category),
+            ));
+        }
+        if shop.charge_large_category.is_some() || shop.charge_middle_category.is_some() {
+            pairs.push((
+                format!("shops.\"{}\" charge", raw),
+                shop.charge_large_category
+                    .as_deref()
+                    .unwrap_or(&skmf.mf_charge_large_category),
+                shop.charge_middle_category
+                    .as_deref()
+                    .unwrap_or(&skmf.mf_charge_middle_category),
+            ));
+        }
+    }
+
+    if let Some(balance) = balance.filter(|it| it.adjust) {
+        if let (Some(large), Some(middle)) = (&balance.large_category, &balance.middle_category) {
+            pairs.push(("balance category".to_string(), large, middle));
+        }
+    }
+    return pairs;
+}
+
+/// creates mf_subaccount as a manual account and middle categories in the config when they are
+/// missing. large categories can not be created. returns what was created.
+pub fn create_missing(
+    skmf: &SkMfConfig,
+    shops: &ShopTable,
+    balance: Option<&BalanceConfig>,
+    mfs: &mut MfSession,
+) -> Result<Vec<String>, String> {
+    let mut created = Vec::new();
+
+    let name = &skmf.mf_subaccount;
+    if !mfs.has_account(name) && !mfs.has_subaccount(name) {
+        mfs.create_manual_account(name)?;
+        mfs.refresh()?;
+        if !mfs.has_account(name) {
+            return Err(format!("failure to create account [{}]", name));
+        }
+        created.push(format!("account [{}]", name));
+    }
+
+    for (_, large, middle) in category_pairs(skmf, shops, balance) {
+        if mfs.has_category(large, middle) || !mfs.categories().contains_key(large) {
+            continue;
+        }
+        mfs.create_middle_category(large, middle)?;
+        mfs.refresh()?;
+        if !mfs.has_category(large, middle) {
+            return Err(format!("failure to create category [{}/{}]", large, middle));
+        }
+        created.push(format!("category [{}/{}]", large, middle));
+    }
+
+    return Ok(created);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get_names() -> MockNames {
+        return toml::from_str(
+            r#"
+            accounts = ["大学生協"]
+            subaccounts = ["大学生協", "財布"]
+            [categories]
+            "食費" = ["外食", "カフェ"]
+            "現金・カード" = ["電子マネー"]
+            "#,
+        )
+        .unwrap();
+    }
+
+    fn get_skmf(charge_middle: &str) -> SkMfConfig {
+        return toml::from_str(&format!(
+            r#"
+            mf_subaccount = "大学生協"
+            mf_large_category = "食費"
+            mf_middle_category = "外食"
+            mf_subaccount_from = "財布"
+            mf_charge_large_category = "現金・カード"
+            mf_charge_middle_category = "{}"
+            "#,
+            charge_middle
+        ))
+        .unwrap();
+    }
+
+    #[test]
+    fn check_config_test() {
+        let names = get_names();
+        let shops: ShopTable =
+            toml::from_str("[\"京大カフェＢ\"]\nmiddle_category=\"カフェ\"\n").unwrap();
+        let skmf = get_skmf("電子マネー");
+        assert!(check_config(&skmf, &shops, None, &names).is_empty());
+
+        let skmf = SkMfConfig {
+            mf_account_subaccount_from: Some("銀行".to_string()),
+            ..get_skmf("電子マネ")
+        };
+        let shops: ShopTable = toml::from_str(
+            "[\"京大カフェＢ\"]\nmiddle_category=\"喫茶\"\ncharge_middle_category=\"チャージ\"\n",
+        )
+        .unwrap();
+        let balance: BalanceConfig = toml::from_str("adjust=true").unwrap();
+        assert_eq!(
+            check_config(&skmf, &shops, Some(&balance), &names),
+            vec![
+                "skmf.mf_account_subaccount_from [銀行] not found in subaccounts",
+                "skmf.mf_charge_large_category/mf_charge_middle_category [現金・カード/電子マネ] not found",
+                "shops.\"京大カフェＢ\" [食費/喫茶] not found",
+                "shops.\"京大カフェＢ\" charge [現金・カード/チャージ] not found",
+                "balance.large_category and middle_category are required to adjust",
+            ]
+        );
+    }
+}
@@ -0,0 +1,135 @@
+use crate::{diff, items, mf, normalize, notify, shops, sk};
+
+/// the whole config file.
+#[derive(serde_derive::Deserialize, Debug)]
+pub struct Config {
+    pub mf: mf::MfUser,
+    pub sk: sk::SkUser,
+    pub skmf: SkMfConfig,
+    pub balance: Option<BalanceConfig>,
+    pub items: Option<items::ItemsConfig>,
+    #[serde(default)]
+    pub shops: shops::ShopTable,
+    pub notify: Option<notify::NotifyConfig>,
+}
+
+/// the [skmf] section: where and how entries are registered.
+#[derive(serde_derive::Deserialize, Debug)]
+pub struct SkMfConfig {
+    pub mf_subaccount: String,
+    pub mf_large_category: String,
+    pub mf_middle_category: String,
+    pub mf_subaccount_from: String,
+    /// 口座入金の振替元(省略時はmf_subaccount_from)
+    pub mf_account_subaccount_from: Option<String>,
+    pub mf_charge_large_category: String,
+    pub mf_charge_middle_category: String,
+    #[serde(default = "default_journal")]
+    pub journal: String,
+    #[serde(default)]
+    pub matching: diff::MatchConfig,
+    /// 店舗名をメモに書く
+    #[serde(default)]
+    pub memo_shop: bool,
+    /// 購入明細の内容
+    #[serde(default = "default_content")]
+    pub prepaid_content: String,
+    /// チャージ明細の内容
+    #[serde(default = "default_content")]
+    pub payment_content: String,
+    #[serde(default)]
+    pub normalize: normalize::NormalizeConfig,
+    /// 大学生協のCSVの壊れた行を飛ばす
+    #[serde(default)]
+    pub lenient_csv: bool,
+}
+
+fn default_journal() -> String {
+    String::from("journal.json")
+}
+
+fn default_content() -> String {
+    String::from("{menu}")
+}
+
+/// the [balance] section.
+#[derive(serde_derive::Deserialize, Debug)]
+pub struct BalanceConfig {
+    /// 残高がずれていたら調整明細を登録する
+    #[serde(default)]
+    pub adjust: bool,
+    #[serde(default = "default_adjust_content")]
+    pub content: String,
+    pub large_category: Option<String>,
+    pub middle_category: Option<String>,
+}
+
+fn default_adjust_content() -> String {
+    String::from("残高調整(skmf)")
+}
+
+/// reads the config file.
+pub fn load_config(path: &str) -> Result<Config, String> {
+    let mut conf = read_config(path)?;
+    conf.mf.pass = resolve_secret(&conf.mf.pass)?;
+    conf.sk.pass = resolve_secret(&conf.sk.pass)?;
+    return Ok(conf);
+}
+
+/// reads the config without resolving the secret references.
+pub fn read_config(path: &str) -> Result<Config, String> {
+    let data =
+        std::fs::read_to_string(path).map_err(|e| format!("conf[{}] load err:{}", path, e))?;
+    let mut conf: Config = toml::from_str(&data).map_err(|e| format!("conf load err:{}", e))?;
+    conf.shops = shops::normalize_keys(conf.shops, &conf.skmf.normalize)?;
+    return Ok(conf);
+}
+
+#[derive(serde_derive::Deserialize)]
+struct MfOnlyConfig {
+    mf: mf::MfUser,
+}
+
+/// reads only the [mf] section of the config file.
+pub fn load_mf_user(path: &str) -> Result<mf::MfUser, String> {
+    let data =
+        std::fs::read_to_string(path).map_err(|e| format!("conf[{}] load err:{}", path, e))?;
+    let mut conf: MfOnlyConfig =
+        toml::from_str(&data).map_err(|e| format!("conf load err:{}", e))?;
+    conf.mf.pass = resolve_secret(&conf.mf.pass)?;
+    return Ok(conf.mf);
+}
+
+/// resolves a secret reference: "env:NAME" is the environment variable and "file:PATH" is the
+/// content of the file. other values are the secret itself.
+pub fn resolve_secret(value: &str) -> Result<String, String> {
+    if let Some(name) = value.strip_prefix("env:") {
+        return std::env::var(name).map_err(|e| format!("secret env[{}] err:{}", name, e));
+    }
+    if let Some(path) = value.strip_prefix("file:") {
+        let data = std::fs::read_to_string(path)
+            .map_err(|e| format!("secret file[{}] load err:{}", path, e))?;
+        return Ok(data.trim_end_matches(&['\r', '\n'][..]).to_string());
+    }
+    return Ok(value.to_string());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolve_secret_test() {
+        assert_eq!(resolve_secret("passwd").unwrap(), "passwd");
+
+        std::env::set_var("SKMF_TEST_SECRET", "from env");
+        assert_eq!(resolve_secret("env:SKMF_TEST_SECRET").unwrap(), "from env");
+        assert!(resolve_secret("env:SKMF_TEST_NO_SUCH_SECRET").is_err());
+
+        assert_eq!(
+            resolve_secret("file:./src/testdata/secret.txt").unwrap(),
+            "s3cret pass"
+        );
+        assert!(resolve_secret("file:./src/testdata/no_such_secret.txt").is_err());
+    }
+}
@@ -0,0 +1,71 @@
+use crate::normalize::{normalize, NormalizeConfig};
+use crate::shops::{get_shop, ShopTable};
+use crate::sk::SkDatum;
+
+/// renders the content template of Moneyforward entries.
+/// {menu}, {shop} (the name in the shop table) and {campus} are replaced.
+/// the result is normalized like the content of Moneyforward entries it is compared with.
+pub fn render_content(
+    template: &str,
+    shops: &ShopTable,
+    norm: &NormalizeConfig,
+    datum: &SkDatum,
+) -> String {
+    let shop = get_shop(shops, &datum.shop, norm, "", "");
+    let content = template
+        .replace("{menu}", &datum.menu)
+        .replace("{shop}", &shop.name)
+        .replace("{campus}", shop.campus.unwrap_or(""));
+    return normalize(norm, content.trim());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sk::make_datum;
+    use chrono::TimeZone;
+    use chrono_tz::Asia::Tokyo;
+
+    #[test]
+    fn render_content_test() {
+        let shops: ShopTable =
+            toml::from_str("[\"京大ルネＤ\"]\nname=\"ルネ食堂\"\ncampus=\"吉田\"\n").unwrap();
+        let norm = NormalizeConfig::default();
+        let shops = crate::shops::normalize_keys(shops, &norm).unwrap();
+        let datum = make_datum(
+            Tokyo.ymd(2022, 7, 5),
+            "京大ルネＤ",
+            "チキンカツカレーM/ほうれん草",
+            473,
+        );
+
+        assert_eq!(
+            render_content("{menu}", &shops, &norm, &datum),
+            "チキンカツカレーM/ほうれん草"
+        );
+        assert_eq!(
+            render_content("{shop}({campus}) {menu}", &shops, &norm, &datum),
+            "ルネ食堂(吉田) チキンカツカレーM/ほうれん草"
+        );
+        // full-width template as written in the config
+        assert_eq!(
+            render_content("{shop}（{campus}）　{menu}", &shops, &norm, &datum),
+            "ルネ食堂(吉田) チキンカツカレーM/ほうれん草"
+        );
+
+        let datum = SkDatum {
+            menu: "".to_string(),
+            shop: "京大生協Ａ".to_string(),
+            deposit: Some(crate::sk::Deposit::Card),
+            ..datum
+        };
+        assert_eq!(
+            render_content("生協チャージ {shop}", &shops, &norm, &datum),
+            "生協チャージ 京大生協A"
+        );
+        assert_eq!(
+            render_content("{shop} {menu}", &shops, &norm, &datum),
+            "京大生協A"
+        );
+    }
+}
@@ -0,0 +1,988 @@
+use crate::items::menu_lines;
+use crate::mf::MfDatum;
+use crate::sk::{menu_items, ItemOf, SkDataType, SkDatum};
+use chrono::{Date, NaiveDate};
+use chrono_tz::Tz;
+use std::collections::{HashMap, VecDeque};
+
+/// how a seikyo record is matched with a registered entry.
+#[derive(serde_derive::Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum MatchStrategy {
+    /// same date, price and menu items. always tried first
+    Exact,
+    /// same price and menu items within `date_tolerance_days`
+    DateTolerance,
+    /// same date and price
+    IgnoreContent,
+    /// same date and price, and the registered content is a prefix of the menu
+    ContentPrefix,
+}
+
+#[derive(serde_derive::Deserialize, Debug)]
+pub struct MatchConfig {
+    #[serde(default)]
+    pub strategies: Vec<MatchStrategy>,
+    #[serde(default = "default_date_tolerance_days")]
+    pub date_tolerance_days: i64,
+}
+
+fn default_date_tolerance_days() -> i64 {
+    1
+}
+
+impl Default for MatchConfig {
+    fn default() -> Self {
+        MatchConfig {
+            strategies: Vec::new(),
+            date_tolerance_days: default_date_tolerance_days(),
+        }
+    }
+}
+
+/// a seikyo record and the Moneyforward entry it corresponds to.
+#[derive(Debug)]
+pub struct DiffRecord<'a> {
+    pub datum: SkDatum,
+    /// the content rendered for Moneyforward
+    pub content: String,
+    /// the registered entry
+    pub matched: Option<&'a MfDatum>,
+    /// why the record was considered already synced
+    pub reason: Option<String>,
+    /// an unmatched entry with the same date and price but different content
+    pub similar: Option<&'a MfDatum>,
+}
+
+#[derive(Debug)]
+pub struct SkMfDiff<'a> {
+    /// seikyo records in the downloaded order
+    pub records: Vec<DiffRecord<'a>>,
+    /// entries of the subaccount without seikyo counterpart (manual mistakes or double posts)
+    pub orphans: Vec<&'a MfDatum>,
+}
+
+impl<'a> SkMfDiff<'a> {
+    /// seikyo records not registered yet
+    pub fn missing(self) -> Vec<SkDatum> {
+        self.records
+            .into_iter()
+            .filter(|it| it.matched.is_none())
+            .map(|it| it.datum)
+            .collect()
+    }
+}
+
+/// converts the signed amount of Moneyforward into seikyo price. entries of the other kind are None.
+fn get_price(c: &MfDatum, skdtype: SkDataType) -> Option<u32> {
+    match skdtype {
+        SkDataType::PrepaidHistory => {
+            if c.price > 0 {
+                return None;
+            }
+            Some(-c.price as u32)
+        }
+        SkDataType::PaymentHistory => {
+            if c.price < 0 {
+                return None;
+            }
+            Some(c.price as u32)
+        }
+    }
+}
+
+/// splits the rendered content into the text before and after the menu.
+/// None when the template has no menu.
+fn split_content<'c>(content: &'c str, menu: &str) -> Option<(&'c str, &'c str)> {
+    if menu.trim() == "" {
+        return None;
+    }
+    let pos = content.find(menu)?;
+    return Some((&content[..pos], &content[pos + menu.len()..]));
+}
+
+/// compares the registered content with the rendered one. menu items may be in any order.
+fn same_content(datum: &SkDatum, content: &str, registered: &str) -> bool {
+    let registered = registered.trim();
+    let (prefix, suffix) = match split_content(content, &datum.menu) {
+        Some(it) => it,
+        None => return registered == content,
+    };
+    return match registered
+        .strip_prefix(prefix)
+        .and_then(|it| it.strip_suffix(suffix))
+    {
+        Some(menu) => menu_items(menu) == menu_items(&datum.menu),
+        None => false,
+    };
+}
+
+/// returns the reason when `c` matches `datum` under the fuzzy strategy.
+fn fuzzy_match(
+    strategy: MatchStrategy,
+    conf: &MatchConfig,
+    datum: &SkDatum,
+    content: &str,
+    c: &MfDatum,
+    price: u32,
+) -> Option<String> {
+    if datum.price != price {
+        return None;
+    }
+    let date = datum.date.naive_local();
+    let mf_date = c.date.naive_local();
+
+    match strategy {
+        MatchStrategy::Exact => None,
+        MatchStrategy::DateTolerance => {
+            let days = (mf_date - date).num_days();
+            if days.abs() > conf.date_tolerance_days || !same_content(datum, content, &c.content) {
+                return None;
+            }
+            Some(format!(
+                "same price and menu registered on {} ({:+} days)",
+                mf_date.format("%Y/%m/%d"),
+                days
+            ))
+        }
+        MatchStrategy::IgnoreContent => {
+            if date != mf_date {
+                return None;
+            }
+            Some(format!(
+                "same date and price, content [{}] ignored",
+                c.content
+            ))
+        }
+        MatchStrategy::ContentPrefix => {
+            let prefix = c.content.trim();
+            if date != mf_date || prefix == "" || !content.starts_with(prefix) {
+                return None;
+            }
+            Some(format!(
+                "same date and price, content [{}] is a prefix of the menu",
+                prefix
+            ))
+        }
+    }
+}
+
+const MARKER_PREFIX: &str = "skmf:";
+
+/// FNV-1a 64bit
+fn fnv1a(data: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for b in data {
+        hash ^= *b as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    return hash;
+}
+
+/// a stable marker of the seikyo record written into the memo of the entry skmf creates.
+/// computed from the raw fields with the menu items sorted, so that neither the normalize rules
+/// nor the item order of seikyo change it. a split item is marked by its record and position,
+/// so that the catalog prices do not change it either.
+pub fn sync_marker(skdtype: SkDataType, datum: &SkDatum) -> String {
+    if let Some(item) = &datum.item_of {
+        return item_marker(&item.marker, item.index);
+    }
+    let kind = match skdtype {
+        SkDataType::PaymentHistory => "payment",
+        SkDataType::PrepaidHistory => "prepaid",
+    };
+    let key = format!(
+        "{}\t{}\t{}\t{}\t{}\t{:?}",
+        kind,
+        datum.date.format("%Y/%m/%d"),
+        datum.shop,
+        menu_items(&datum.raw_menu).join("/"),
+        datum.price,
+        datum.deposit
+    );
+    return format!("{}{:012x}", MARKER_PREFIX, fnv1a(key.as_bytes()) >> 16);
+}
+
+fn item_marker(parent: &str, index: usize) -> String {
+    let key = format!("{}\t{}", parent, index);
+    return format!("{}{:012x}", MARKER_PREFIX, fnv1a(key.as_bytes()) >> 16);
+}
+
+/// a marker of the balance adjustment skmf creates. `delta` is signed as the Moneyforward price.
+pub fn balance_marker(date: Date<Tz>, delta: i64) -> String {
+    let key = format!("balance\t{}\t{}", date.format("%Y/%m/%d"), delta);
+    return format!("{}{:012x}", MARKER_PREFIX, fnv1a(key.as_bytes()) >> 16);
+}
+
+/// whether the entry is a balance adjustment skmf created. it has no seikyo record.
+pub fn is_adjustment(c: &MfDatum) -> bool {
+    return find_marker(&c.memo) == Some(balance_marker(c.date, c.price as i64).as_str());
+}
+
+/// finds the marker in the memo of a Moneyforward entry.
+fn find_marker(memo: &str) -> Option<&str> {
+    let start = memo.find(MARKER_PREFIX)?;
+    let len = memo[start + MARKER_PREFIX.len()..]
+        .find(|c: char| !c.is_ascii_hexdigit())
+        .unwrap_or(memo.len() - start - MARKER_PREFIX.len());
+    if len == 0 {
+        return None;
+    }
+    return Some(&memo[start..start + MARKER_PREFIX.len() + len]);
+}
+
+/// matches seikyo records with Moneyforward entries.
+///
+/// entries carrying the marker of a record are matched first, even if content or amount was edited.
+/// the rest are matched as multisets keyed by date, price and content with menu items in any order,
+/// and records left unmatched are tried again with the fuzzy strategies of `conf` in order.
+///
+/// `render` must be the same content template used when sending.
+pub fn get_skmf_diff<'a>(
+    mfdata: &'a [MfDatum],
+    skdata: Vec<SkDatum>,
+    skdtype: SkDataType,
+    conf: &MatchConfig,
+    render: &dyn Fn(&SkDatum) -> String,
+) -> SkMfDiff<'a> {
+    return get_split_diff(mfdata, skdata, skdtype, conf, render, &|it| it);
+}
+
+/// same as `get_skmf_diff`, but records not registered as a whole are split by `split` and
+/// matched again per item. entries registered before the split are kept as they are.
+pub fn get_split_diff<'a>(
+    mfdata: &'a [MfDatum],
+    skdata: Vec<SkDatum>,
+    skdtype: SkDataType,
+    conf: &MatchConfig,
+    render: &dyn Fn(&SkDatum) -> String,
+    split: &dyn Fn(Vec<SkDatum>) -> Vec<SkDatum>,
+) -> SkMfDiff<'a> {
+    let mut buckets = HashMap::<(NaiveDate, u32), Vec<usize>>::new();
+    for (i, c) in mfdata.iter().enumerate() {
+        if let Some(price) = get_price(c, skdtype) {
+            buckets
+                .entry((c.date.naive_local(), price))
+                .or_default()
+                .push(i);
+        }
+    }
+
+    let mut markers = HashMap::<&str, VecDeque<usize>>::new();
+    for (i, c) in mfdata.iter().enumerate() {
+        if let Some(marker) = find_marker(&c.memo) {
+            markers.entry(marker).or_default().push_back(i);
+        }
+    }
+
+    let mut used: Vec<bool> = mfdata.iter().map(is_adjustment).collect();
+    let mut records: Vec<DiffRecord> = skdata
+        .into_iter()
+        .map(|it| new_record(it, render))
+        .collect();
+    match_records(
+        mfdata,
+        &mut records,
+        &buckets,
+        &mut markers,
+        &mut used,
+        skdtype,
+        conf,
+    );
+
+    let mut items = Vec::new();
+    for it in records {
+        if it.matched.is_some() {
+            items.push(it);
+            continue;
+        }
+        let parent = sync_marker(skdtype, &it.datum);
+        let mut pieces = split(vec![it.datum]);
+        if pieces.len() > 1 {
+            for (index, piece) in pieces.iter_mut().enumerate() {
+                piece.item_of = Some(ItemOf {
+                    marker: parent.clone(),
+                    index,
+                });
+            }
+            items.extend(pieces.into_iter().map(|it| new_record(it, render)));
+            continue;
+        }
+
+        // items sent before the catalog changed. the record does not split any more.
+        let mut it = match pieces.pop() {
+            Some(datum) => new_record(datum, render),
+            None => continue,
+        };
+        let count = menu_lines(&it.datum.menu).len();
+        for index in 0..count {
+            let marker = item_marker(&parent, index);
+            if let Some(i) = markers
+                .get_mut(marker.as_str())
+                .and_then(|it| it.pop_front())
+            {
+                used[i] = true;
+                if it.matched.is_none() {
+                    it.matched = Some(&mfdata[i]);
+                    it.reason = Some(format!("marker {} split into items", parent));
+                }
+            }
+        }
+        items.push(it);
+    }
+    let mut records = items;
+    match_records(
+        mfdata,
+        &mut records,
+        &buckets,
+        &mut markers,
+        &mut used,
+        skdtype,
+        conf,
+    );
+
+    // pairs left on both sides with the same date and price differ only in content.
+    let mut similars = HashMap::<(NaiveDate, u32), VecDeque<usize>>::new();
+    for (i, c) in mfdata.iter().enumerate() {
+        if used[i] {
+            continue;
+        }
+        if let Some(price) = get_price(c, skdtype) {
+            similars
+                .entry((c.date.naive_local(), price))
+                .or_default()
+                .push_back(i);
+        }
+    }
+    for it in records.iter_mut().filter(|it| it.matched.is_none()) {
+        let key = (it.datum.date.naive_local(), it.datum.price);
+        if let Some(i) = similars.get_mut(&key).and_then(|it| it.pop_front()) {
+            used[i] = true;
+            it.similar = Some(&mfdata[i]);
+        }
+    }
+
+    let orphans = mfdata
+        .iter()
+        .enumerate()
+        .filter(|(i, c)| !used[*i] && get_price(c, skdtype).is_some())
+        .map(|(_, c)| c)
+        .collect();
+
+    return SkMfDiff { records, orphans };
+}
+
+fn new_record<'a>(datum: SkDatum, render: &dyn Fn(&SkDatum) -> String) -> DiffRecord<'a> {
+    return DiffRecord {
+        content: render(&datum),
+        datum,
+        matched: None,
+        reason: None,
+        similar: None,
+    };
+}
+
+/// matches the unmatched records by marker, exactly and then fuzzily. matched entries are `used`.
+fn match_records<'a>(
+    mfdata: &'a [MfDatum],
+    records: &mut [DiffRecord<'a>],
+    buckets: &HashMap<(NaiveDate, u32), Vec<usize>>,
+    markers: &mut HashMap<&str, VecDeque<usize>>,
+    used: &mut [bool],
+    skdtype: SkDataType,
+    conf: &MatchConfig,
+) {
+    for it in records.iter_mut().filter(|it| it.matched.is_none()) {
+        let marker = sync_marker(skdtype, &it.datum);
+        if let Some(i) = markers
+            .get_mut(marker.as_str())
+            .and_then(|it| it.pop_front())
+        {
+            used[i] = true;
+            it.matched = Some(&mfdata[i]);
+            it.reason = Some(format!("marker {}", marker));
+        }
+    }
+
+    for it in records.iter_mut().filter(|it| it.matched.is_none()) {
+        let key = (it.datum.date.naive_local(), it.datum.price);
+        // entries matched by marker are skipped.
+        let matched = buckets.get(&key).and_then(|bucket| {
+            bucket
+                .iter()
+                .copied()
+                .find(|i| !used[*i] && same_content(&it.datum, &it.content, &mfdata[*i].content))
+        });
+        if let Some(i) = matched {
+            used[i] = true;
+            it.matched = Some(&mfdata[i]);
+            it.reason = Some("exact".to_string());
+        }
+    }
+
+    for strategy in &conf.strategies {
+        for it in records.iter_mut().filter(|it| it.matched.is_none()) {
+            let found = mfdata.iter().enumerate().find_map(|(i, c)| {
+                if used[i] {
+                    return None;
+                }
+                let reason = fuzzy_match(
+                    *strategy,
+                    conf,
+                    &it.datum,
+                    &it.content,
+                    c,
+                    get_price(c, skdtype)?,
+                )?;
+                Some((i, reason))
+            });
+            if let Some((i, reason)) = found {
+                used[i] = true;
+                it.matched = Some(&mfdata[i]);
+                it.reason = Some(reason);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sk::make_datum;
+    use chrono::{Date, TimeZone};
+    use chrono_tz::{Asia::Tokyo, Tz};
+
+    #[test]
+    fn test_diff_prepaid() {
+        let mf_dummy = vec![
+            make_dummy_mfdatum(Tokyo.ymd(2022, 7, 10), "menu1", -120, "id1"),
+            make_dummy_mfdatum(Tokyo.ymd(2022, 7, 11), "menu2", -123, "id2"),
+            make_dummy_mfdatum(Tokyo.ymd(2022, 7, 11), "menu2", -123, "id3"),
+            make_dummy_mfdatum(Tokyo.ymd(2022, 7, 13), "menu2", -123, "id4"),
+            make_dummy_mfdatum(Tokyo.ymd(2022, 7, 13), "menu3", -125, "id5"),
+            make_dummy_mfdatum(Tokyo.ymd(2022, 7, 14), "menu1", -120, "id6"),
+        ];
+
+        let sk_dummy = vec![
+            make_datum(Tokyo.ymd(2022, 7, 10), "", "menu1", 120), // registered
+            make_datum(Tokyo.ymd(2022, 7, 11), "", "menu2", 123), // registered
+            make_datum(Tokyo.ymd(2022, 7, 11), "", "menu2", 123), // registered
+            make_datum(Tokyo.ymd(2022, 7, 11), "", "menu2", 123),
+            make_datum(Tokyo.ymd(2022, 7, 13), "", "menu2", 123), // registered
+            make_datum(Tokyo.ymd(2022, 7, 13), "", "menu3", 125), // registered
+            make_datum(Tokyo.ymd(2022, 7, 14), "", "menu2", 123),
+            make_datum(Tokyo.ymd(2022, 7, 14), "", "menu1", 120), // registered
+            make_datum(Tokyo.ymd(2022, 7, 14), "", "menu4", 129),
+            make_datum(Tokyo.ymd(2022, 7, 15), "", "menu4", 129),
+        ];
+
+        let sk_want_result = vec![
+            make_datum(Tokyo.ymd(2022, 7, 11), "", "menu2", 123),
+            make_datum(Tokyo.ymd(2022, 7, 14), "", "menu2", 123),
+            make_datum(Tokyo.ymd(2022, 7, 14), "", "menu4", 129),
+            make_datum(Tokyo.ymd(2022, 7, 15), "", "menu4", 129),
+        ];
+
+        let result = get_skmf_diff(
+            &mf_dummy,
+            sk_dummy,
+            SkDataType::PrepaidHistory,
+            &MatchConfig::default(),
+            &menu,
+        )
+        .missing();
+
+        assert_eq!(result.len(), sk_want_result.len());
+        for (i, it) in result.iter().enumerate() {
+            assert!(compare_sk(it, &sk_want_result[i]));
+        }
+    }
+
+    #[test]
+    fn test_diff_payment() {
+        let mf_dummy = vec![
+            make_dummy_mfdatum(Tokyo.ymd(2022, 7, 10), "", 1000, "id1"),
+            make_dummy_mfdatum(Tokyo.ymd(2022, 7, 11), "", 1000, "id2"),
+            make_dummy_mfdatum(Tokyo.ymd(2022, 7, 11), "", 1000, "id3"),
+            make_dummy_mfdatum(Tokyo.ymd(2022, 7, 13), "", 1000, "id4"),
+            make_dummy_mfdatum(Tokyo.ymd(2022, 7, 13), "", 1000, "id5"),
+            make_dummy_mfdatum(Tokyo.ymd(2022, 7, 14), "", 1000, "id6"),
+        ];
+
+        let sk_dummy = vec![
+            make_datum(Tokyo.ymd(2022, 7, 10), "", "", 1000), // registered
+            make_datum(Tokyo.ymd(2022, 7, 11), "", "", 1000), // registered
+            make_datum(Tokyo.ymd(2022, 7, 11), "", "", 1000), // registered
+            make_datum(Tokyo.ymd(2022, 7, 11), "", "", 1000),
+            make_datum(Tokyo.ymd(2022, 7, 13), "", "", 1000), // registered
+            make_datum(Tokyo.ymd(2022, 7, 13), "", "", 1000), // registered
+            make_datum(Tokyo.ymd(2022, 7, 14), "", "", 1000),
+            make_datum(Tokyo.ymd(2022, 7, 14), "", "", 1000), // registered
+            make_datum(Tokyo.ymd(2022, 7, 14), "", "", 1000),
+            make_datum(Tokyo.ymd(2022, 7, 15), "", "", 1000),
+        ];
+
+        let sk_want_result = vec![
+            make_datum(Tokyo.ymd(2022, 7, 11), "", "", 1000),
+            make_datum(Tokyo.ymd(2022, 7, 14), "", "", 1000),
+            make_datum(Tokyo.ymd(2022, 7, 14), "", "", 1000),
+            make_datum(Tokyo.ymd(2022, 7, 15), "", "", 1000),
+        ];
+
+        let result = get_skmf_diff(
+            &mf_dummy,
+            sk_dummy,
+            SkDataType::PaymentHistory,
+            &MatchConfig::default(),
+            &menu,
+        )
+        .missing();
+
+        assert_eq!(result.len(), sk_want_result.len());
+        for (i, it) in result.iter().enumerate() {
+            assert!(compare_sk(it, &sk_want_result[i]));
+        }
+    }
+
+    #[test]
+    fn test_diff_reordered_menu() {
+        let mf_dummy = vec![
+            make_dummy_mfdatum(
+                Tokyo.ymd(2022, 7, 5),
+                "味噌汁/ほうれん草/ローストンカツごまソ",
+                -407,
+                "id1",
+            ),
+            make_dummy_mfdatum(
+                Tokyo.ymd(2022, 7, 5),
+                "味噌汁/ほうれん草/ローストンカツごまソ",
+                -407,
+                "id2",
+            ),
+        ];
+
+        // same purchases downloaded again in another order
+        let sk_dummy = vec![
+            make_datum(
+                Tokyo.ymd(2022, 7, 5),
+                "",
+                "ローストンカツごまソ/味噌汁/ほうれん草",
+                407,
+            ),
+            make_datum(
+                Tokyo.ymd(2022, 7, 5),
+                "",
+                "ほうれん草/ローストンカツごまソ/味噌汁",
+                407,
+            ),
+            make_datum(
+                Tokyo.ymd(2022, 7, 5),
+                "",
+                "味噌汁/ほうれん草/ローストンカツごまソ",
+                407,
+            ),
+        ];
+
+        let result = get_skmf_diff(
+            &mf_dummy,
+            sk_dummy,
+            SkDataType::PrepaidHistory,
+            &MatchConfig::default(),
+            &menu,
+        )
+        .missing();
+
+        assert_eq!(result.len(), 1);
+        assert!(compare_sk(
+            &result[0],
+            &make_datum(
+                Tokyo.ymd(2022, 7, 5),
+                "",
+                "味噌汁/ほうれん草/ローストンカツごまソ",
+                407
+            )
+        ));
+    }
+
+    #[test]
+    fn test_diff_orphans_and_similars() {
+        let mf_dummy = vec![
+            make_dummy_mfdatum(Tokyo.ymd(2022, 7, 1), "menu1", -100, "id1"),
+            make_dummy_mfdatum(Tokyo.ymd(2022, 7, 1), "menu1", -100, "id2"), // double post
+            make_dummy_mfdatum(Tokyo.ymd(2022, 7, 2), "menu2 edited", -200, "id3"),
+            make_dummy_mfdatum(Tokyo.ymd(2022, 7, 3), "", 1000, "id4"), // payment
+        ];
+
+        let sk_dummy = vec![
+            make_datum(Tokyo.ymd(2022, 7, 1), "", "menu1", 100),
+            make_datum(Tokyo.ymd(2022, 7, 2), "", "menu2", 200),
+            make_datum(Tokyo.ymd(2022, 7, 2), "", "menu3", 200),
+        ];
+
+        let result = get_skmf_diff(
+            &mf_dummy,
+            sk_dummy,
+            SkDataType::PrepaidHistory,
+            &MatchConfig::default(),
+            &menu,
+        );
+
+        assert_eq!(result.records[0].matched.unwrap().id, "id1");
+        assert!(result.records[1].matched.is_none());
+        assert_eq!(result.records[1].similar.unwrap().id, "id3");
+        assert!(result.records[2].matched.is_none());
+        assert!(result.records[2].similar.is_none());
+
+        assert_eq!(result.orphans.len(), 1);
+        assert_eq!(result.orphans[0].id, "id2");
+    }
+
+    #[test]
+    fn test_diff_fuzzy() {
+        let mf_dummy = vec![
+            make_dummy_mfdatum(Tokyo.ymd(2022, 7, 2), "menu1", -100, "id1"), // date corrected
+            make_dummy_mfdatum(Tokyo.ymd(2022, 7, 3), "唐揚げ", -473, "id2"), // shortened
+            make_dummy_mfdatum(Tokyo.ymd(2022, 7, 4), "昼食", -407, "id3"),  // rewritten
+            make_dummy_mfdatum(Tokyo.ymd(2022, 7, 9), "menu5", -500, "id4"),
+        ];
+
+        let make_sk_dummy = || {
+            vec![
+                make_datum(Tokyo.ymd(2022, 7, 1), "", "menu1", 100),
+                make_datum(Tokyo.ymd(2022, 7, 3), "", "唐揚げカレーM/ほうれん草", 473),
+                make_datum(Tokyo.ymd(2022, 7, 4), "", "味噌汁/ほうれん草", 407),
+                make_datum(Tokyo.ymd(2022, 7, 5), "", "menu5", 500),
+            ]
+        };
+
+        let result = get_skmf_diff(
+            &mf_dummy,
+            make_sk_dummy(),
+            SkDataType::PrepaidHistory,
+            &MatchConfig::default(),
+            &menu,
+        );
+        assert_eq!(result.missing().len(), 4);
+
+        let conf = MatchConfig {
+            strategies: vec![
+                MatchStrategy::DateTolerance,
+                MatchStrategy::ContentPrefix,
+                MatchStrategy::IgnoreContent,
+            ],
+            date_tolerance_days: 2,
+        };
+        let result = get_skmf_diff(
+            &mf_dummy,
+            make_sk_dummy(),
+            SkDataType::PrepaidHistory,
+            &conf,
+            &menu,
+        );
+        assert_eq!(result.records[0].matched.unwrap().id, "id1");
+        assert_eq!(
+            result.records[0].reason.as_ref().unwrap(),
+            "same price and menu registered on 2022/07/02 (+1 days)"
+        );
+        assert_eq!(result.records[1].matched.unwrap().id, "id2");
+        assert_eq!(
+            result.records[1].reason.as_ref().unwrap(),
+            "same date and price, content [唐揚げ] is a prefix of the menu"
+        );
+        assert_eq!(result.records[2].matched.unwrap().id, "id3");
+        assert_eq!(
+            result.records[2].reason.as_ref().unwrap(),
+            "same date and price, content [昼食] ignored"
+        );
+        // out of tolerance
+        assert!(result.records[3].matched.is_none());
+        assert_eq!(result.orphans.len(), 1);
+    }
+
+    #[test]
+    fn match_config_test() {
+        let conf: MatchConfig =
+            toml::from_str("strategies=[\"date_tolerance\",\"content_prefix\"]").unwrap();
+        assert_eq!(
+            conf.strategies,
+            vec![MatchStrategy::DateTolerance, MatchStrategy::ContentPrefix]
+        );
+        assert_eq!(conf.date_tolerance_days, 1);
+        assert!(toml::from_str::<MatchConfig>("strategies=[\"fuzzy\"]").is_err());
+    }
+
+    #[test]
+    fn test_diff_content_template() {
+        let mf_dummy = vec![
+            make_dummy_mfdatum(Tokyo.ymd(2022, 7, 4), "ルネ menu1/menu2", -200, "id1"),
+            make_dummy_mfdatum(Tokyo.ymd(2022, 7, 5), "menu3", -300, "id2"),
+        ];
+        let sk_dummy = vec![
+            make_datum(Tokyo.ymd(2022, 7, 4), "", "menu1/menu2", 200),
+            make_datum(Tokyo.ymd(2022, 7, 5), "", "menu3", 300),
+        ];
+
+        let render = |it: &SkDatum| format!("ルネ {}", it.menu);
+        let result = get_skmf_diff(
+            &mf_dummy,
+            sk_dummy,
+            SkDataType::PrepaidHistory,
+            &MatchConfig::default(),
+            &render,
+        );
+        assert_eq!(result.records[0].content, "ルネ menu1/menu2");
+        assert_eq!(result.records[0].matched.unwrap().id, "id1");
+        // registered before the template was set
+        assert!(result.records[1].matched.is_none());
+        assert_eq!(result.records[1].similar.unwrap().id, "id2");
+    }
+
+    #[test]
+    fn test_diff_content_template_reordered() {
+        let mf_dummy = vec![
+            make_dummy_mfdatum(Tokyo.ymd(2022, 7, 4), "ルネ 唐揚げ/ごはん", -200, "id1"),
+            make_dummy_mfdatum(Tokyo.ymd(2022, 7, 4), "ルネ 唐揚げ/ごはん", -200, "id2"),
+            make_dummy_mfdatum(Tokyo.ymd(2022, 7, 5), "カフェ ごはん/唐揚げ", -200, "id3"),
+        ];
+        let sk_dummy = vec![
+            make_datum(Tokyo.ymd(2022, 7, 4), "", "ごはん/唐揚げ", 200),
+            make_datum(Tokyo.ymd(2022, 7, 4), "", "唐揚げ/ごはん", 200),
+            make_datum(Tokyo.ymd(2022, 7, 5), "", "ごはん/唐揚げ", 200),
+        ];
+
+        let render = |it: &SkDatum| format!("ルネ {}", it.menu);
+        let conf = toml::from_str("strategies=[\"date_tolerance\"]").unwrap();
+        let result = get_skmf_diff(
+            &mf_dummy,
+            sk_dummy,
+            SkDataType::PrepaidHistory,
+            &conf,
+            &render,
+        );
+        assert_eq!(result.records[0].matched.unwrap().id, "id1");
+        assert_eq!(result.records[0].reason.as_ref().unwrap(), "exact");
+        assert_eq!(result.records[1].matched.unwrap().id, "id2");
+        // same menu items but the text around the menu differs
+        assert!(result.records[2].matched.is_none());
+        assert_eq!(result.records[2].similar.unwrap().id, "id3");
+    }
+
+    #[test]
+    fn test_split_diff() {
+        let mf_dummy = vec![
+            // registered as a whole before split mode
+            make_dummy_mfdatum(Tokyo.ymd(2022, 7, 4), "menu1/menu2", -300, "id1"),
+            make_dummy_mfdatum(Tokyo.ymd(2022, 7, 5), "menu1", -100, "id2"),
+        ];
+        let sk_dummy = vec![
+            make_datum(Tokyo.ymd(2022, 7, 4), "", "menu1/menu2", 300),
+            make_datum(Tokyo.ymd(2022, 7, 5), "", "menu1/menu2", 300),
+        ];
+        let split = |skdata: Vec<SkDatum>| {
+            skdata
+                .into_iter()
+                .flat_map(|it| {
+                    vec![
+                        make_datum(it.date, "", "menu1", 100),
+                        make_datum(it.date, "", "menu2", 200),
+                    ]
+                })
+                .collect()
+        };
+        let result = get_split_diff(
+            &mf_dummy,
+            sk_dummy,
+            SkDataType::PrepaidHistory,
+            &MatchConfig::default(),
+            &menu,
+            &split,
+        );
+
+        let got: Vec<_> = result
+            .records
+            .iter()
+            .map(|it| (it.datum.menu.as_str(), it.matched.map(|c| c.id.as_str())))
+            .collect();
+        assert_eq!(
+            got,
+            vec![
+                ("menu1/menu2", Some("id1")),
+                ("menu1", Some("id2")),
+                ("menu2", None),
+            ]
+        );
+        assert!(result.orphans.is_empty());
+    }
+
+    #[test]
+    fn test_split_diff_catalog_changed() {
+        let record = || make_datum(Tokyo.ymd(2022, 7, 4), "", "menu1/menu2", 300);
+        let parent = sync_marker(SkDataType::PrepaidHistory, &record());
+        let mut item1 = make_dummy_mfdatum(Tokyo.ymd(2022, 7, 4), "menu1", -100, "id1");
+        item1.memo = item_marker(&parent, 0);
+        let mut item2 = make_dummy_mfdatum(Tokyo.ymd(2022, 7, 4), "menu2", -200, "id2");
+        item2.memo = item_marker(&parent, 1);
+        let mf_dummy = vec![item1, item2];
+
+        // the prices of the catalog changed
+        let split = |skdata: Vec<SkDatum>| {
+            skdata
+                .into_iter()
+                .flat_map(|it| {
+                    vec![
+                        make_datum(it.date, "", "menu1", 150),
+                        make_datum(it.date, "", "menu2", 150),
+                    ]
+                })
+                .collect()
+        };
+        let result = get_split_diff(
+            &mf_dummy,
+            vec![record()],
+            SkDataType::PrepaidHistory,
+            &MatchConfig::default(),
+            &menu,
+            &split,
+        );
+        let got: Vec<_> = result
+            .records
+            .iter()
+            .map(|it| (it.datum.menu.as_str(), it.matched.map(|c| c.id.as_str())))
+            .collect();
+        assert_eq!(got, vec![("menu1", Some("id1")), ("menu2", Some("id2"))]);
+        assert!(result.orphans.is_empty());
+
+        // an item was removed from the catalog
+        let result = get_split_diff(
+            &mf_dummy,
+            vec![record()],
+            SkDataType::PrepaidHistory,
+            &MatchConfig::default(),
+            &menu,
+            &|it| it,
+        );
+        assert_eq!(result.records.len(), 1);
+        assert_eq!(result.records[0].matched.unwrap().id, "id1");
+        assert!(result.orphans.is_empty());
+    }
+
+    #[test]
+    fn test_diff_marker() {
+        let sk_dummy = vec![
+            make_datum(Tokyo.ymd(2022, 7, 4), "", "menu1", 200),
+            make_datum(Tokyo.ymd(2022, 7, 4), "", "menu1", 200),
+            make_datum(Tokyo.ymd(2022, 7, 5), "", "menu2", 300),
+        ];
+        let marker = sync_marker(SkDataType::PrepaidHistory, &sk_dummy[0]);
+        assert!(marker.starts_with("skmf:"));
+        assert_eq!(marker.len(), 17);
+        assert_ne!(
+            marker,
+            sync_marker(SkDataType::PaymentHistory, &sk_dummy[0])
+        );
+
+        let mut mf_dummy = vec![
+            make_dummy_mfdatum(Tokyo.ymd(2022, 7, 4), "menu1", -200, "id1"),
+            // edited in the Moneyforward UI
+            make_dummy_mfdatum(Tokyo.ymd(2022, 7, 4), "昼ごはん", -250, "id2"),
+            make_dummy_mfdatum(Tokyo.ymd(2022, 7, 5), "menu2", -300, "id3"),
+        ];
+        mf_dummy[1].memo = format!("ルネ食堂\n{}", marker);
+
+        let result = get_skmf_diff(
+            &mf_dummy,
+            sk_dummy,
+            SkDataType::PrepaidHistory,
+            &MatchConfig::default(),
+            &menu,
+        );
+        assert_eq!(result.records[0].matched.unwrap().id, "id2");
+        assert_eq!(
+            result.records[0].reason.as_ref().unwrap(),
+            &format!("marker {}", marker)
+        );
+        assert_eq!(result.records[1].matched.unwrap().id, "id1");
+        assert_eq!(result.records[1].reason.as_ref().unwrap(), "exact");
+        assert_eq!(result.records[2].matched.unwrap().id, "id3");
+        assert!(result.orphans.is_empty());
+    }
+
+    #[test]
+    fn sync_marker_test() {
+        let datum = make_datum(Tokyo.ymd(2022, 7, 4), "京大ルネＤ", "唐揚げ/ごはん", 300);
+        let marker = sync_marker(SkDataType::PrepaidHistory, &datum);
+
+        // seikyo reordered the items
+        let reordered = make_datum(Tokyo.ymd(2022, 7, 4), "京大ルネＤ", "ごはん/唐揚げ", 300);
+        assert_eq!(sync_marker(SkDataType::PrepaidHistory, &reordered), marker);
+
+        // the normalize rules changed
+        let renormalized = SkDatum {
+            menu: "唐揚げ / ごはん".to_string(),
+            ..make_datum(Tokyo.ymd(2022, 7, 4), "京大ルネＤ", "唐揚げ/ごはん", 300)
+        };
+        assert_eq!(
+            sync_marker(SkDataType::PrepaidHistory, &renormalized),
+            marker
+        );
+
+        let other = make_datum(Tokyo.ymd(2022, 7, 4), "京大ルネＤ", "唐揚げ/みそ汁", 300);
+        assert_ne!(sync_marker(SkDataType::PrepaidHistory, &other), marker);
+    }
+
+    #[test]
+    fn test_diff_adjustment() {
+        let mut adjustment = make_dummy_mfdatum(Tokyo.ymd(2022, 7, 3), "残高調整", -30, "id1");
+        adjustment.memo = balance_marker(Tokyo.ymd(2022, 7, 3), -30);
+        let mut edited = make_dummy_mfdatum(Tokyo.ymd(2022, 7, 3), "残高調整", -40, "id2");
+        edited.memo = balance_marker(Tokyo.ymd(2022, 7, 3), -30);
+        let mf_dummy = vec![adjustment, edited];
+
+        let result = get_skmf_diff(
+            &mf_dummy,
+            vec![],
+            SkDataType::PrepaidHistory,
+            &MatchConfig::default(),
+            &menu,
+        );
+
+        // the amount of the adjustment was edited by hand
+        assert_eq!(result.orphans.len(), 1);
+        assert_eq!(result.orphans[0].id, "id2");
+    }
+
+    #[test]
+    fn find_marker_test() {
+        assert_eq!(find_marker("skmf:0123abcdef45"), Some("skmf:0123abcdef45"));
+        assert_eq!(
+            find_marker("唐揚げ\nskmf:0123abcdef45\n"),
+            Some("skmf:0123abcdef45")
+        );
+        assert_eq!(find_marker("skmf:"), None);
+        assert_eq!(find_marker("昼食"), None);
+    }
+
+    fn menu(it: &SkDatum) -> String {
+        it.menu.clone()
+    }
+
+    fn compare_sk(i: &SkDatum, j: &SkDatum) -> bool {
+        return i.date == j.date && i.menu == j.menu && i.price == j.price && i.shop == j.shop;
+    }
+
+    fn make_dummy_mfdatum(date: Date<Tz>, content: &str, price: i32, id: &str) -> MfDatum {
+        return MfDatum {
+            target: true,
+            date: date,
+            content: content.to_string(),
+            price: price,
+            bank: "".to_string(),
+            category: "".to_string(),
+            subcategory: "".to_string(),
+            memo: "".to_string(),
+            transfer: false,
+            id: id.to_string(),
+            extra: Default::default(),
+        };
+    }
+}
@@ -0,0 +1,172 @@
+use crate::mf::MfAccountCategory;
+use std::collections::HashMap;
+
+/// keys of the map in order.
+pub fn sorted_names<V>(map: &HashMap<String, V>) -> Vec<&str> {
+    let mut names: Vec<&str> = map.keys().map(|it| it.as_str()).collect();
+    names.sort();
+    return names;
+}
+
+pub(crate) fn quote(text: &str) -> String {
+    return toml::Value::String(text.to_string()).to_string();
+}
+
+/// writes the first name as the value and the others commented out.
+fn push_choices(text: &mut String, key: &str, names: &[&str], select: bool) {
+    for (i, name) in names.iter().enumerate() {
+        let comment = if select && i == 0 { "" } else { "# " };
+        text.push_str(&format!("{}{}={}\n", comment, key, quote(name)));
+    }
+}
+
+/// lists accounts, whose history is compared, and subaccounts, which entries are registered to.
+pub fn format_accounts(
+    accounts: &HashMap<String, String>,
+    subaccounts: &HashMap<String, String>,
+) -> String {
+    let mut text = "accounts:\n".to_string();
+    for name in sorted_names(accounts) {
+        text.push_str(&format!("  {}\n", name));
+    }
+    text.push_str("subaccounts:\n");
+    for name in sorted_names(subaccounts) {
+        text.push_str(&format!("  {}\n", name));
+    }
+    return text;
+}
+
+/// [skmf] snippet of the accounts. mf_subaccount must be both an account and a subaccount.
+pub fn format_accounts_toml(
+    accounts: &HashMap<String, String>,
+    subaccounts: &HashMap<String, String>,
+) -> String {
+    let names = sorted_names(subaccounts);
+    let targets = target_names(accounts, subaccounts);
+
+    let mut text = "[skmf]\n".to_string();
+    if targets.is_empty() {
+        text.push_str("# 口座と支出元の両方にあるものが見つかりません\n");
+        push_choices(&mut text, "mf_subaccount", &names, false);
+    } else {
+        push_choices(&mut text, "mf_subaccount", &targets, true);
+    }
+
+    let from: Vec<&str> = names
+        .iter()
+        .copied()
+        .filter(|it| targets.first() != Some(it))
+        .collect();
+    push_choices(&mut text, "mf_subaccount_from", &from, true);
+    push_choices(&mut text, "mf_account_subaccount_from", &from, false);
+    return text;
+}
+
+/// names for mf_subaccount, which must be both an account and a subaccount.
+pub fn target_names<'a>(
+    accounts: &HashMap<String, String>,
+    subaccounts: &'a HashMap<String, String>,
+) -> Vec<&'a str> {
+    return sorted_names(subaccounts)
+        .into_iter()
+        .filter(|it| accounts.contains_key(*it))
+        .collect();
+}
+
+/// lists large categories and their middle categories.
+pub fn format_categories(categories: &HashMap<String, MfAccountCategory>) -> String {
+    let mut text = String::new();
+    for name in sorted_names(categories) {
+        text.push_str(&format!("{}\n", name));
+        for middle in sorted_names(&categories[name].subcategory) {
+            text.push_str(&format!("  {}\n", middle));
+        }
+    }
+    return text;
+}
+
+/// [skmf] snippet of every pair of large and middle categories, all commented out.
+pub fn format_categories_toml(categories: &HashMap<String, MfAccountCategory>) -> String {
+    let mut text = "[skmf]\n".to_string();
+    text.push_str(
+        "# 使う組み合わせのコメントを外す(チャージはmf_charge_large_category/mf_charge_middle_category)\n",
+    );
+    for name in sorted_names(categories) {
+        for middle in sorted_names(&categories[name].subcategory) {
+            text.push_str(&format!("\n# {} / {}\n", name, middle));
+            push_choices(&mut text, "mf_large_category", &[name], false);
+            push_choices(&mut text, "mf_middle_category", &[middle], false);
+        }
+    }
+    return text;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn map(names: &[&str]) -> HashMap<String, String> {
+        return names
+            .iter()
+            .map(|it| (it.to_string(), format!("id_{}", it)))
+            .collect();
+    }
+
+    #[test]
+    fn format_accounts_test() {
+        let accounts = map(&["大学生協", "みずほ銀行"]);
+        let subaccounts = map(&["財布", "大学生協", "ヨドバシカード"]);
+
+        assert_eq!(
+            format_accounts(&accounts, &subaccounts),
+            "accounts:\n  みずほ銀行\n  大学生協\nsubaccounts:\n  ヨドバシカード\n  大学生協\n  財布\n"
+        );
+        let text = format_accounts_toml(&accounts, &subaccounts);
+        assert_eq!(
+            text,
+            concat!(
+                "[skmf]\n",
+                "mf_subaccount=\"大学生協\"\n",
+                "mf_subaccount_from=\"ヨドバシカード\"\n",
+                "# mf_subaccount_from=\"財布\"\n",
+                "# mf_account_subaccount_from=\"ヨドバシカード\"\n",
+                "# mf_account_subaccount_from=\"財布\"\n",
+            )
+        );
+        assert!(toml::from_str::<toml::Value>(&text).is_ok());
+
+        let text = format_accounts_toml(&map(&[]), &map(&["財布"]));
+        assert!(text.contains("# mf_subaccount=\"財布\"\nmf_subaccount_from=\"財布\"\n"));
+    }
+
+    #[test]
+    fn format_categories_test() {
+        let mut categories = HashMap::new();
+        categories.insert(
+            "食費".to_string(),
+            MfAccountCategory {
+                name: "食費".to_string(),
+                id: "11".to_string(),
+                subcategory: map(&["外食", "食料品"]),
+            },
+        );
+        categories.insert(
+            "\"特別\"な支出".to_string(),
+            MfAccountCategory {
+                name: "\"特別\"な支出".to_string(),
+                id: "12".to_string(),
+                subcategory: map(&["旅行"]),
+            },
+        );
+
+        assert_eq!(
+            format_categories(&categories),
+            "\"特別\"な支出\n  旅行\n食費\n  外食\n  食料品\n"
+        );
+        let text = format_categories_toml(&categories);
+        assert!(text.contains(
+            "\n# 食費 / 外食\n# mf_large_category=\"食費\"\n# mf_middle_category=\"外食\"\n"
+        ));
+        assert!(text.contains("# mf_large_category=\"\\\"特別\\\"な支出\"\n"));
+    }
+}
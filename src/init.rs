@@ -0,0 +1,115 @@
+use crate::discover::quote;
+use std::io::Write;
+
+/// answers of `skmf init`. passwords may be secret references like "env:MF_PASS".
+#[derive(Debug, Default)]
+pub struct InitAnswers {
+    pub mf_email: String,
+    pub mf_pass: String,
+    pub sk_user: String,
+    pub sk_pass: String,
+    pub mf_subaccount: String,
+    pub mf_subaccount_from: String,
+    pub mf_large_category: String,
+    pub mf_middle_category: String,
+    pub mf_charge_large_category: String,
+    pub mf_charge_middle_category: String,
+}
+
+/// the config file in the layout of config.toml-dist.
+pub fn format_config(answers: &InitAnswers) -> String {
+    let mut text = String::new();
+    let mut section = |name: &str, values: &[(&str, &str)]| {
+        if text != "" {
+            text.push('\n');
+        }
+        text.push_str(&format!("[{}]\n", name));
+        for (key, value) in values {
+            text.push_str(&format!("{}={}\n", key, quote(value)));
+        }
+    };
+    section(
+        "mf",
+        &[("email", &answers.mf_email), ("pass", &answers.mf_pass)],
+    );
+    section(
+        "sk",
+        &[("user", &answers.sk_user), ("pass", &answers.sk_pass)],
+    );
+    section(
+        "skmf",
+        &[
+            ("mf_subaccount", &answers.mf_subaccount),
+            ("mf_large_category", &answers.mf_large_category),
+            ("mf_middle_category", &answers.mf_middle_category),
+            ("mf_subaccount_from", &answers.mf_subaccount_from),
+            (
+                "mf_charge_large_category",
+                &answers.mf_charge_large_category,
+            ),
+            (
+                "mf_charge_middle_category",
+                &answers.mf_charge_middle_category,
+            ),
+        ],
+    );
+    return text;
+}
+
+/// writes the config readable only by the owner, as it may contain passwords.
+/// an existing file is kept unless `overwrite`.
+pub fn write_config(path: &str, text: &str, overwrite: bool) -> Result<(), String> {
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true);
+    if overwrite {
+        options.create(true).truncate(true);
+    } else {
+        options.create_new(true);
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+        options.mode(0o600);
+        // the mode is applied only to a new file.
+        if overwrite && std::path::Path::new(path).exists() {
+            std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))
+                .map_err(|e| format!("conf[{}] chmod err:{}", path, e))?;
+        }
+    }
+
+    let mut file = options
+        .open(path)
+        .map_err(|e| format!("conf[{}] open err:{}", path, e))?;
+    file.write_all(text.as_bytes())
+        .map_err(|e| format!("conf[{}] write err:{}", path, e))?;
+    return Ok(());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    #[test]
+    fn format_config_test() {
+        let answers = InitAnswers {
+            mf_email: "me@example.com".to_string(),
+            mf_pass: "env:MF_PASS".to_string(),
+            sk_user: "12345".to_string(),
+            sk_pass: "file:/etc/skmf/sk_pass".to_string(),
+            mf_subaccount: "大学生協".to_string(),
+            mf_subaccount_from: "財布".to_string(),
+            mf_large_category: "食費".to_string(),
+            mf_middle_category: "外食".to_string(),
+            mf_charge_large_category: "現金・カード".to_string(),
+            mf_charge_middle_category: "電子マネー".to_string(),
+        };
+        let text = format_config(&answers);
+        assert!(text.starts_with("[mf]\nemail=\"me@example.com\"\npass=\"env:MF_PASS\"\n\n[sk]\n"));
+
+        let conf: Config = toml::from_str(&text).unwrap();
+        assert_eq!(conf.sk.pass, "file:/etc/skmf/sk_pass");
+        assert_eq!(conf.skmf.mf_charge_middle_category, "電子マネー");
+        assert_eq!(conf.skmf.journal, "journal.json");
+    }
+}
@@ -0,0 +1,230 @@
+use crate::sk::SkDatum;
+use log::info;
+use std::collections::BTreeMap;
+
+/// how a purchase of several items is registered.
+#[derive(serde_derive::Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ItemMode {
+    /// one entry as is
+    None,
+    /// one entry with the item list in the memo
+    Memo,
+    /// one entry per item priced by the catalog
+    Split,
+}
+
+impl Default for ItemMode {
+    fn default() -> Self {
+        ItemMode::None
+    }
+}
+
+#[derive(serde_derive::Deserialize, Debug)]
+pub struct ItemsConfig {
+    #[serde(default)]
+    pub mode: ItemMode,
+    /// 品目ごとの価格表
+    #[serde(default = "default_catalog")]
+    pub catalog: String,
+}
+
+pub fn default_catalog() -> String {
+    String::from("catalog.toml")
+}
+
+/// item prices maintained by the user or learned by `skmf catalog`.
+#[derive(serde_derive::Deserialize, serde_derive::Serialize, Debug, Default)]
+pub struct Catalog {
+    #[serde(default)]
+    pub prices: BTreeMap<String, u32>,
+    /// 品目ごとの月("YYYY/MM")別の価格
+    #[serde(default)]
+    pub history: BTreeMap<String, BTreeMap<String, u32>>,
+}
+
+pub struct Items {
+    pub mode: ItemMode,
+    pub catalog: Catalog,
+}
+
+pub fn load_catalog(path: &str) -> Result<Catalog, String> {
+    let data =
+        std::fs::read_to_string(path).map_err(|e| format!("catalog[{}] load err:{}", path, e))?;
+    let catalog: Catalog =
+        toml::from_str(&data).map_err(|e| format!("catalog[{}] parse err:{}", path, e))?;
+    return Ok(catalog);
+}
+
+pub fn save_catalog(path: &str, catalog: &Catalog) -> Result<(), String> {
+    let data =
+        toml::to_string(catalog).map_err(|e| format!("catalog[{}] serialize err:{}", path, e))?;
+    let tmp = format!("{}.tmp", path);
+    std::fs::write(&tmp, data).map_err(|e| format!("catalog[{}] write err:{}", tmp, e))?;
+    std::fs::rename(&tmp, path).map_err(|e| format!("catalog[{}] rename err:{}", path, e))?;
+    return Ok(());
+}
+
+/// the catalog is only read in split mode.
+pub fn load_items(conf: Option<&ItemsConfig>) -> Result<Items, String> {
+    let mode = conf.map(|it| it.mode).unwrap_or_default();
+    let catalog = match conf {
+        Some(conf) if mode == ItemMode::Split => load_catalog(&conf.catalog)?,
+        _ => Catalog::default(),
+    };
+    return Ok(Items { mode, catalog });
+}
+
+/// items of the menu in the purchased order.
+pub fn menu_lines(menu: &str) -> Vec<String> {
+    menu.split("/")
+        .map(|it| it.trim().to_string())
+        .filter(|it| it != "")
+        .collect()
+}
+
+impl Items {
+    /// splits records into items in split mode.
+    /// a record is kept as is when the catalog cannot account for its total.
+    pub fn expand(&self, skdata: Vec<SkDatum>) -> Vec<SkDatum> {
+        if self.mode != ItemMode::Split {
+            return skdata;
+        }
+
+        let mut records = Vec::new();
+        for datum in skdata {
+            if menu_lines(&datum.menu).len() <= 1 {
+                records.push(datum);
+                continue;
+            }
+            match self.split(&datum) {
+                Ok(items) => records.extend(items),
+                Err(e) => {
+                    info!(
+                        "{} {}円 [{}] not split: {}",
+                        datum.date.format("%Y/%m/%d"),
+                        datum.price,
+                        datum.menu,
+                        e
+                    );
+                    records.push(datum);
+                }
+            }
+        }
+        return records;
+    }
+
+    fn split(&self, datum: &SkDatum) -> Result<Vec<SkDatum>, String> {
+        // items are marked by their raw names when normalization kept the number of items.
+        let lines = menu_lines(&datum.menu);
+        let raw_lines = menu_lines(&datum.raw_menu);
+        let mut items = Vec::new();
+        for (i, line) in lines.iter().enumerate() {
+            let price = self
+                .catalog
+                .prices
+                .get(line)
+                .ok_or(format!("[{}] not in catalog", line))?;
+            let raw = if raw_lines.len() == lines.len() {
+                &raw_lines[i]
+            } else {
+                line
+            };
+            items.push(SkDatum {
+                date: datum.date,
+                price: *price,
+                shop: datum.shop.clone(),
+                menu: line.clone(),
+                raw_menu: raw.clone(),
+                deposit: datum.deposit,
+                item_of: None,
+            });
+        }
+
+        let total: u32 = items.iter().map(|it| it.price).sum();
+        if total != datum.price {
+            return Err(format!("catalog total {}円", total));
+        }
+        return Ok(items);
+    }
+
+    /// memo for the record in memo mode. empty otherwise.
+    pub fn memo(&self, menu: &str) -> String {
+        if self.mode != ItemMode::Memo {
+            return String::new();
+        }
+        return menu_lines(menu).join("\n");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sk::make_datum;
+    use chrono::TimeZone;
+    use chrono_tz::Asia::Tokyo;
+
+    fn make_items(mode: ItemMode) -> Items {
+        let catalog: Catalog = toml::from_str(
+            "[prices]\n\"チキンカツカレーM\"=407\n\"ほうれん草\"=66\n\"鶏きも煮\"=88\n",
+        )
+        .unwrap();
+        Items { mode, catalog }
+    }
+
+    #[test]
+    fn expand_test() {
+        let items = make_items(ItemMode::Split);
+        let records = items.expand(vec![
+            make_datum(
+                Tokyo.ymd(2022, 7, 5),
+                "shop",
+                "チキンカツカレーM/ほうれん草/鶏きも煮",
+                561,
+            ),
+            make_datum(
+                Tokyo.ymd(2022, 7, 5),
+                "shop",
+                "チキンカツカレーM/ほうれん草",
+                500,
+            ), // price changed
+            make_datum(Tokyo.ymd(2022, 7, 5), "shop", "チキンカツカレーM/冷奴", 473), // unknown item
+            make_datum(Tokyo.ymd(2022, 7, 5), "shop", "鶏きも煮", 88),
+        ]);
+
+        let got: Vec<_> = records
+            .iter()
+            .map(|it| (it.menu.as_str(), it.price))
+            .collect();
+        assert_eq!(
+            got,
+            vec![
+                ("チキンカツカレーM", 407),
+                ("ほうれん草", 66),
+                ("鶏きも煮", 88),
+                ("チキンカツカレーM/ほうれん草", 500),
+                ("チキンカツカレーM/冷奴", 473),
+                ("鶏きも煮", 88),
+            ]
+        );
+
+        let items = make_items(ItemMode::Memo);
+        assert_eq!(
+            items
+                .expand(vec![make_datum(Tokyo.ymd(2022, 7, 5), "shop", "a/b", 1)])
+                .len(),
+            1
+        );
+    }
+
+    #[test]
+    fn memo_test() {
+        let items = make_items(ItemMode::Memo);
+        assert_eq!(
+            items.memo("チキンカツカレーM/ ほうれん草 /鶏きも煮"),
+            "チキンカツカレーM\nほうれん草\n鶏きも煮"
+        );
+        let items = make_items(ItemMode::Split);
+        assert_eq!(items.memo("チキンカツカレーM/ほうれん草"), "");
+    }
+}
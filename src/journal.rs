@@ -0,0 +1,309 @@
+use crate::mf::MfDatum;
+use crate::sk::{same_menu, SkDataType, SkDatum};
+use chrono::Date;
+use chrono_tz::Tz;
+use std::fs::File;
+use std::io::BufReader;
+use std::io::BufWriter;
+
+#[derive(serde_derive::Serialize, serde_derive::Deserialize, Debug, Clone, PartialEq)]
+pub enum JournalState {
+    /// POST開始前に記録
+    Pending,
+    /// POST成功を確認済み
+    Done,
+}
+
+#[derive(serde_derive::Serialize, serde_derive::Deserialize, Debug, Clone)]
+pub struct JournalEntry {
+    pub kind: String,
+    pub date: String,
+    pub price: u32,
+    pub menu: String,
+    pub state: JournalState,
+}
+
+/// write-ahead journal of records sent to Moneyforward.
+#[derive(Debug)]
+pub struct Journal {
+    path: String,
+    entries: Vec<JournalEntry>,
+}
+
+pub fn load_journal(path: &str) -> Result<Journal, String> {
+    let entries = match File::open(path) {
+        Ok(f) => serde_json::from_reader(BufReader::new(f))
+            .map_err(|e| format!("journal[{}] broken:{}", path, e))?,
+        // the first run. other errors must not disable the journal.
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+        Err(e) => return Err(format!("journal[{}] open err:{}", path, e)),
+    };
+
+    return Ok(Journal {
+        path: path.to_string(),
+        entries,
+    });
+}
+
+pub fn save_journal(journal: &Journal) -> Result<(), String> {
+    let tmp = format!("{}.tmp", journal.path);
+    {
+        let file = BufWriter::new(File::create(&tmp).map_err(|e| e.to_string())?);
+        serde_json::to_writer_pretty(file, &journal.entries).map_err(|e| e.to_string())?;
+    }
+    std::fs::rename(&tmp, &journal.path).map_err(|e| e.to_string())?;
+
+    return Ok(());
+}
+
+fn kind_name(dtype: SkDataType) -> &'static str {
+    match dtype {
+        SkDataType::PaymentHistory => "payment",
+        SkDataType::PrepaidHistory => "prepaid",
+    }
+}
+
+/// the kind of balance adjustments. the menu holds the marker written into the memo.
+const BALANCE_KIND: &str = "balance";
+
+fn format_date(date: Date<Tz>) -> String {
+    date.format("%Y/%m/%d").to_string()
+}
+
+fn is_same(entry: &JournalEntry, kind: &str, datum: &SkDatum) -> bool {
+    entry.kind == kind
+        && entry.date == format_date(datum.date)
+        && entry.price == datum.price
+        && same_menu(&entry.menu, &datum.menu)
+}
+
+impl Journal {
+    /// records `datum` as pending before it is sent. returns the entry index.
+    pub fn begin(&mut self, dtype: SkDataType, datum: &SkDatum) -> Result<usize, String> {
+        self.entries.push(JournalEntry {
+            kind: kind_name(dtype).to_string(),
+            date: format_date(datum.date),
+            price: datum.price,
+            menu: datum.menu.clone(),
+            state: JournalState::Pending,
+        });
+        save_journal(self)?;
+        return Ok(self.entries.len() - 1);
+    }
+
+    /// marks the entry as sent after a confirmed POST.
+    pub fn commit(&mut self, index: usize) -> Result<(), String> {
+        let entry = self
+            .entries
+            .get_mut(index)
+            .ok_or("journal entry not found")?;
+        entry.state = JournalState::Done;
+        return save_journal(self);
+    }
+
+    /// records a balance adjustment as pending before it is sent. returns the entry index.
+    pub fn begin_adjustment(
+        &mut self,
+        date: Date<Tz>,
+        delta: i64,
+        marker: &str,
+    ) -> Result<usize, String> {
+        self.entries.push(JournalEntry {
+            kind: BALANCE_KIND.to_string(),
+            date: format_date(date),
+            price: delta.unsigned_abs() as u32,
+            menu: marker.to_string(),
+            state: JournalState::Pending,
+        });
+        save_journal(self)?;
+        return Ok(self.entries.len() - 1);
+    }
+
+    /// removes balance adjustments which Moneyforward shows now.
+    pub fn settle_adjustments(&mut self, mfdata: &[MfDatum]) {
+        self.entries.retain(|entry| {
+            entry.kind != BALANCE_KIND || !mfdata.iter().any(|c| c.memo.contains(&entry.menu))
+        });
+    }
+
+    /// whether every entry sent was indexed by Moneyforward.
+    /// the balance of Moneyforward is not reliable until then.
+    pub fn is_settled(&self) -> bool {
+        return self.entries.is_empty();
+    }
+
+    /// reconciles the journal of the month with records missing from Moneyforward.
+    ///
+    /// records already sent are dropped even if Moneyforward does not show them yet.
+    /// records left pending by a previous run are returned first so they are resent before new ones.
+    /// entries which Moneyforward shows now are removed from the journal.
+    pub fn reconcile(
+        &mut self,
+        dtype: SkDataType,
+        month: Date<Tz>,
+        missing: Vec<SkDatum>,
+    ) -> Vec<SkDatum> {
+        let kind = kind_name(dtype);
+        let prefix = month.format("%Y/%m/").to_string();
+
+        let mut consumed = vec![false; self.entries.len()];
+        let mut resend = Vec::new();
+        let mut fresh = Vec::new();
+
+        'skloop: for it in missing {
+            for (i, entry) in self.entries.iter().enumerate() {
+                if consumed[i] || !is_same(entry, kind, &it) {
+                    continue;
+                }
+                consumed[i] = true;
+                match entry.state {
+                    JournalState::Done => {}
+                    JournalState::Pending => resend.push(it),
+                }
+                continue 'skloop;
+            }
+            fresh.push(it);
+        }
+
+        // keep only sent entries Moneyforward has not indexed yet.
+        let mut i = 0;
+        self.entries.retain(|entry| {
+            let keep = !(entry.kind == kind && entry.date.starts_with(&prefix))
+                || (consumed[i] && entry.state == JournalState::Done);
+            i += 1;
+            keep
+        });
+
+        resend.extend(fresh);
+        return resend;
+    }
+
+    /// drops entries older than `month`. they are out of the synchronized range.
+    pub fn prune(&mut self, month: Date<Tz>) {
+        let oldest = format_date(month);
+        self.entries.retain(|entry| entry.date >= oldest);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sk::make_datum;
+    use chrono::TimeZone;
+    use chrono_tz::Asia::Tokyo;
+
+    fn make_journal(entries: Vec<JournalEntry>) -> Journal {
+        Journal {
+            path: "".to_string(),
+            entries,
+        }
+    }
+
+    fn make_entry(date: &str, menu: &str, price: u32, state: JournalState) -> JournalEntry {
+        JournalEntry {
+            kind: "prepaid".to_string(),
+            date: date.to_string(),
+            price,
+            menu: menu.to_string(),
+            state,
+        }
+    }
+
+    #[test]
+    fn load_journal_test() {
+        let journal = load_journal("./src/testdata/no_such_journal.json").unwrap();
+        assert!(journal.entries.is_empty());
+        // a path under a file can not be opened, but it is not the first run either.
+        let result = load_journal("./src/testdata/secret.txt/journal.json");
+        assert!(result.unwrap_err().contains("open err"));
+    }
+
+    #[test]
+    fn reconcile_test() {
+        let mut journal = make_journal(vec![
+            make_entry("2022/07/01", "menu1", 100, JournalState::Done),
+            make_entry("2022/07/02", "menu2", 200, JournalState::Done),
+            make_entry("2022/07/03", "menu3", 300, JournalState::Pending),
+            make_entry("2022/07/04", "menu4", 400, JournalState::Pending),
+            make_entry("2022/06/30", "menu0", 100, JournalState::Done),
+        ]);
+
+        let missing = vec![
+            make_datum(Tokyo.ymd(2022, 7, 1), "", "menu1", 100), // sent, not indexed yet
+            make_datum(Tokyo.ymd(2022, 7, 3), "", "menu3", 300), // pending, not posted
+            make_datum(Tokyo.ymd(2022, 7, 5), "", "menu5", 500), // new
+        ];
+
+        let result = journal.reconcile(SkDataType::PrepaidHistory, Tokyo.ymd(2022, 7, 1), missing);
+
+        assert_eq!(result.len(), 2);
+        assert_eq!(result[0].menu, "menu3");
+        assert_eq!(result[1].menu, "menu5");
+
+        assert_eq!(journal.entries.len(), 2);
+        assert_eq!(journal.entries[0].menu, "menu1");
+        assert_eq!(journal.entries[1].menu, "menu0");
+    }
+
+    #[test]
+    fn reconcile_duplicated_test() {
+        let mut journal = make_journal(vec![make_entry(
+            "2022/07/01",
+            "menu1",
+            100,
+            JournalState::Done,
+        )]);
+
+        let missing = vec![
+            make_datum(Tokyo.ymd(2022, 7, 1), "", "menu1", 100),
+            make_datum(Tokyo.ymd(2022, 7, 1), "", "menu1", 100),
+        ];
+
+        let result = journal.reconcile(SkDataType::PrepaidHistory, Tokyo.ymd(2022, 7, 1), missing);
+        assert_eq!(result.len(), 1);
+        assert_eq!(journal.entries.len(), 1);
+    }
+
+    #[test]
+    fn settle_adjustments_test() {
+        let mut journal = make_journal(vec![
+            JournalEntry {
+                kind: BALANCE_KIND.to_string(),
+                ..make_entry("2022/07/03", "skmf:0123abcdef45", 30, JournalState::Done)
+            },
+            JournalEntry {
+                kind: BALANCE_KIND.to_string(),
+                ..make_entry("2022/07/04", "skmf:543210fedcba", 20, JournalState::Done)
+            },
+        ]);
+        assert!(!journal.is_settled());
+
+        let indexed = MfDatum {
+            target: true,
+            date: Tokyo.ymd(2022, 7, 3),
+            content: "残高調整".to_string(),
+            price: -30,
+            bank: "".to_string(),
+            category: "".to_string(),
+            subcategory: "".to_string(),
+            memo: "skmf:0123abcdef45".to_string(),
+            transfer: false,
+            id: "id1".to_string(),
+            extra: Default::default(),
+        };
+        journal.settle_adjustments(&[indexed]);
+        assert_eq!(journal.entries.len(), 1);
+        assert_eq!(journal.entries[0].menu, "skmf:543210fedcba");
+    }
+
+    #[test]
+    fn prune_test() {
+        let mut journal = make_journal(vec![
+            make_entry("2022/05/31", "menu1", 100, JournalState::Done),
+            make_entry("2022/06/01", "menu2", 200, JournalState::Done),
+        ]);
+        journal.prune(Tokyo.ymd(2022, 6, 1));
+        assert_eq!(journal.entries.len(), 1);
+        assert_eq!(journal.entries[0].menu, "menu2");
+    }
+}
@@ -0,0 +1,30 @@
+//! skmf: registers the history of the seikyo prepaid to Moneyforward ME.
+//!
+//! the `skmf` binary is built on this library. the clients can also be used alone:
+//!
+//! - [`mf::MfSession`] lists accounts and categories of Moneyforward ME, fetches the history
+//!   and registers entries.
+//! - [`sk::SkClient`] fetches the purchase and payment history and the balance of the seikyo
+//!   prepaid.
+//! - [`sync::run`] does what the binary does without a subcommand.
+
+pub mod catalog;
+pub mod check;
+pub mod config;
+pub mod content;
+pub mod diff;
+pub mod discover;
+pub mod init;
+pub mod items;
+pub mod journal;
+pub mod logging;
+pub mod metrics;
+pub mod mf;
+pub mod normalize;
+pub mod notify;
+pub mod report;
+pub mod shops;
+pub mod sk;
+pub mod stats;
+pub mod summary;
+pub mod sync;
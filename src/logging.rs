@@ -0,0 +1,116 @@
+use env_logger::Env;
+use log::LevelFilter;
+use std::io::Write;
+
+/// initializes env_logger. the level is taken from RUST_LOG (default: info).
+///
+/// ureq writes request headers including cookies and CSRF tokens at debug level,
+/// so its level is capped to info regardless of RUST_LOG.
+pub fn init_logger(json: bool) {
+    let mut builder = env_logger::Builder::from_env(Env::default().default_filter_or("info"));
+    builder.filter_module("ureq", LevelFilter::Info);
+
+    if json {
+        builder.format(|buf, record| {
+            let line = serde_json::json!({
+                "timestamp": chrono::Utc::now().to_rfc3339(),
+                "level": record.level().as_str(),
+                "target": record.target(),
+                "message": record.args().to_string(),
+            });
+            writeln!(buf, "{}", line)
+        });
+    }
+    builder.init();
+}
+
+const SECRET_KEYS: [&str; 7] = [
+    "pass",
+    "token",
+    "cookie",
+    "csrf",
+    "secret",
+    "session",
+    "authenticator",
+];
+
+fn is_secret(key: &str) -> bool {
+    let key = key.to_lowercase();
+    SECRET_KEYS.iter().any(|it| key.contains(it))
+}
+
+/// formats form fields for debug tracing with secret values masked.
+pub fn redact_form(form: &[(&str, &str)]) -> String {
+    form.iter()
+        .map(|(k, v)| {
+            if is_secret(k) && *v != "" {
+                format!("{}=***", k)
+            } else {
+                format!("{}={}", k, v)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+/// masks secret query parameters of the url for debug tracing.
+pub fn redact_url(url: &str) -> String {
+    let mut target = match url::Url::parse(url) {
+        Ok(target) => target,
+        Err(_) => return url.to_string(),
+    };
+    if target.query().is_none() {
+        return url.to_string();
+    }
+
+    let queries: Vec<(String, String)> = target
+        .query_pairs()
+        .map(|(k, v)| {
+            let v = if is_secret(&k) {
+                "***".to_string()
+            } else {
+                v.to_string()
+            };
+            (k.to_string(), v)
+        })
+        .collect();
+    target.query_pairs_mut().clear().extend_pairs(queries);
+
+    return target.to_string();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn redact_form_test() {
+        let form = [
+            ("authenticity_token", "iMPAh10Er0Kt38BEshIGs8Zk"),
+            ("mfid_user[email]", "user@example.com"),
+            ("mfid_user[password]", "hunter2"),
+            ("hiddenPassword", ""),
+            ("user_asset_act[amount]", "473"),
+        ];
+        assert_eq!(
+            redact_form(&form),
+            "authenticity_token=***&mfid_user[email]=user@example.com&mfid_user[password]=***&hiddenPassword=&user_asset_act[amount]=473"
+        );
+    }
+
+    #[test]
+    fn redact_url_test() {
+        assert_eq!(
+            redact_url("https://moneyforward.com/cf/csv?account_id_hash=abc&year=2022"),
+            "https://moneyforward.com/cf/csv?account_id_hash=abc&year=2022"
+        );
+        assert_eq!(
+            redact_url("https://id.moneyforward.com/sign_in?client_id=x&session_token=y"),
+            "https://id.moneyforward.com/sign_in?client_id=x&session_token=***"
+        );
+        assert_eq!(
+            redact_url("https://moneyforward.com/"),
+            "https://moneyforward.com/"
+        );
+    }
+}
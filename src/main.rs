@@ -1,30 +1,14 @@
-mod mf;
-mod sk;
-use chrono::Date;
-use chrono::Datelike;
-
-use chrono::TimeZone;
-use chrono::Utc;
-use chrono_tz::{Asia::Tokyo, Tz};
 use argh::FromArgs;
-use mf::send_datum;
-
-#[derive(serde_derive::Deserialize, Debug)]
-struct Config {
-    mf: mf::MfUser,
-    sk: sk::SkUser,
-    skmf: SkMfConfig,
-}
-
-#[derive(serde_derive::Deserialize, Debug)]
-struct SkMfConfig {
-    mf_subaccount: String,
-    mf_large_category: String,
-    mf_middle_category: String,
-    mf_subaccount_from: String,
-    mf_charge_large_category: String,
-    mf_charge_middle_category: String,
-}
+use chrono::Utc;
+use chrono_tz::Asia::Tokyo;
+use log::{error, info};
+use skmf::config::{load_config, load_mf_user, read_config, resolve_secret, Config};
+use skmf::sync::{get_date, get_past_date};
+use skmf::{
+    catalog, check, discover, init, items, logging, metrics, mf, notify, report, sk, stats,
+    summary, sync,
+};
+use std::io::Write;
 
 #[derive(Debug,FromArgs)]
 /// skmf: seikyo to moneyforward data transporter
@@ -33,278 +17,476 @@ struct Args{
     #[argh(option, default = "String::from(\"config.toml\")")]
     /// path for config file. default value is "config.toml"
     config:String,
+
+    #[argh(switch)]
+    /// write logs as JSON lines (for journald ingestion)
+    log_json: bool,
+
+    #[argh(option)]
+    /// path to write the run report as JSON
+    report: Option<String>,
+
+    #[argh(option)]
+    /// path to write Prometheus metrics for the textfile collector
+    metrics: Option<String>,
+
+    #[argh(switch)]
+    /// create the account and middle categories in the config if missing
+    create_missing: bool,
+
+    #[argh(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Debug, FromArgs)]
+#[argh(subcommand)]
+enum Command {
+    Catalog(CatalogCommand),
+    Stats(StatsCommand),
+    Mf(MfCommand),
+    Check(CheckCommand),
+    Init(InitCommand),
+}
+
+#[derive(Debug, FromArgs)]
+#[argh(subcommand, name = "catalog")]
+/// learn item prices from the prepaid history and store them in the catalog
+struct CatalogCommand {
+    #[argh(option, default = "12")]
+    /// number of months to read back. default value is 12
+    months: u32,
+}
+
+#[derive(Debug, FromArgs)]
+#[argh(subcommand, name = "stats")]
+/// print spending statistics of the seikyo history
+struct StatsCommand {
+    #[argh(option)]
+    /// first month like "2022-04". default value is 11 months before --to
+    from: Option<String>,
+
+    #[argh(option)]
+    /// last month like "2023-03". default value is this month
+    to: Option<String>,
+
+    #[argh(option, default = "10")]
+    /// number of items to list. default value is 10
+    top: usize,
+}
+
+#[derive(Debug, FromArgs)]
+#[argh(subcommand, name = "mf")]
+/// list names in Moneyforward to write the [skmf] section
+struct MfCommand {
+    #[argh(subcommand)]
+    command: MfSubCommand,
+}
+
+#[derive(Debug, FromArgs)]
+#[argh(subcommand)]
+enum MfSubCommand {
+    Accounts(MfAccountsCommand),
+    Categories(MfCategoriesCommand),
+}
+
+#[derive(Debug, FromArgs)]
+#[argh(subcommand, name = "accounts")]
+/// list accounts and subaccounts
+struct MfAccountsCommand {
+    #[argh(switch)]
+    /// print as a config snippet
+    toml: bool,
+}
+
+#[derive(Debug, FromArgs)]
+#[argh(subcommand, name = "categories")]
+/// list large and middle categories
+struct MfCategoriesCommand {
+    #[argh(switch)]
+    /// print as a config snippet
+    toml: bool,
+}
+
+#[derive(Debug, FromArgs)]
+#[argh(subcommand, name = "check")]
+/// check that names in the config exist in Moneyforward
+struct CheckCommand {
+    #[argh(option)]
+    /// check against names in this file instead of logging in
+    mock: Option<String>,
+}
+
+#[derive(Debug, FromArgs)]
+#[argh(subcommand, name = "init")]
+/// ask credentials and names in Moneyforward, then write the config
+struct InitCommand {
+    #[argh(switch)]
+    /// overwrite the existing config
+    force: bool,
 }
 
 fn main() {
-    match do_main() {
-        Ok(_) => {}
-        Err(msg) => println!("Error:{}", msg),
+    let arg:Args = argh::from_env();
+    logging::init_logger(arg.log_json);
+    info!("using config:{}",arg.config);
+
+    if let Some(Command::Init(cmd)) = &arg.command {
+        if let Err(msg) = init_config(&arg.config, cmd) {
+            error!("{}", msg);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    // only [mf] is needed, as the rest of the config is written from its output.
+    if let Some(Command::Mf(cmd)) = &arg.command {
+        if let Err(msg) = list_mf(&arg.config, cmd) {
+            error!("{}", msg);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    // the mock check does not log in, so the secrets are not needed.
+    let conf = match &arg.command {
+        Some(Command::Check(CheckCommand { mock: Some(_) })) => read_config(&arg.config),
+        _ => load_config(&arg.config),
+    };
+    let conf = match conf {
+        Ok(conf) => conf,
+        Err(msg) => {
+            error!("{}", msg);
+            std::process::exit(1);
+        }
+    };
+    let result = match &arg.command {
+        Some(Command::Catalog(cmd)) => update_catalog(conf, cmd),
+        Some(Command::Stats(cmd)) => print_stats(conf, cmd),
+        Some(Command::Check(cmd)) => check_config(conf, cmd, arg.create_missing),
+        Some(Command::Mf(_)) | Some(Command::Init(_)) | None => {
+            return run_sync(conf, &arg);
+        }
+    };
+    if let Err(msg) = result {
+        error!("{}", msg);
+        std::process::exit(1);
     }
 }
 
-fn do_main() -> Result<(), String> {
-    let arg:Args = argh::from_env();
-    println!("using config:{}",arg.config);
+/// syncs the records, then writes the report and metrics and notifies the result.
+fn run_sync(mut conf: Config, arg: &Args) {
+    let notify = conf.notify.take();
 
-    let data =
-        std::fs::read_to_string(&arg.config).map_err(|e| format!("conf[{}] load err:{}",arg.config,e))?;
-    let conf: Config = toml::from_str(&data).map_err(|e| format!("conf load err:{}", e))?;
+    let mut summary = summary::RunSummary::default();
+    let mut report = report::RunReport::default();
+    if let Err(msg) = sync::run(conf, arg.create_missing, &mut summary, &mut report) {
+        error!("{}", msg);
+        summary.errors.push(msg);
+    }
+    let mut failed = !summary.is_success();
 
-    let mfs = mf::get_mf_session(conf.mf)?;
-    let ska = sk::get_sk_agent(conf.sk)?;
+    if let Some(path) = &arg.report {
+        report.errors = summary.errors.clone();
+        if let Err(msg) = report::save_report(path, &report) {
+            error!("report:{}", msg);
+            failed = true;
+        }
+    }
+
+    if let Some(path) = &arg.metrics {
+        if let Err(msg) = metrics::save_metrics(path, &report, &summary, Utc::now().timestamp()) {
+            error!("metrics:{}", msg);
+            failed = true;
+        }
+    }
 
-    let date = get_date(Tokyo);
-    println!("start(1) at {}", date);
-    if let Err(e) = send_skmf(&mfs, &ska, date, &conf.skmf) {
-        mf::save_mf_session(mfs)?;
-        return Err(e);
+    if let Some(notify) = notify {
+        if let Err(msg) = notify::send_notification(&notify, &summary) {
+            error!("notify:{}", msg);
+            failed = true;
+        }
     }
 
-    let date = get_past_date(date);
-    println!("start(2) at {}", date);
-    if let Err(e) = send_skmf(&mfs, &ska, date, &conf.skmf) {
-        mf::save_mf_session(mfs)?;
-        return Err(e);
+    if failed {
+        std::process::exit(1);
     }
+}
 
-    mf::save_mf_session(mfs)?;
-    Ok(())
+/// reads a line from stdin. empty answers are asked again.
+fn ask(prompt: &str) -> Result<String, String> {
+    loop {
+        let answer = read_answer(prompt)?;
+        if answer != "" {
+            return Ok(answer);
+        }
+    }
 }
 
-fn get_date(tz: Tz) -> Date<Tz> {
-    let utcdate = Utc::today().naive_utc();
-    let date = tz.from_utc_date(&utcdate);
+fn read_answer(prompt: &str) -> Result<String, String> {
+    print!("{}: ", prompt);
+    std::io::stdout()
+        .flush()
+        .map_err(|e| format!("{}: {}", prompt, e))?;
+    let mut line = String::new();
+    let len = std::io::stdin()
+        .read_line(&mut line)
+        .map_err(|e| format!("{}: {}", prompt, e))?;
+    if len == 0 {
+        return Err(format!("{}: no answer", prompt));
+    }
+    return Ok(line.trim().to_string());
+}
 
-    tz.ymd(date.year(), date.month(), 1)
+/// the input is hidden by stty where it is available.
+fn ask_secret(prompt: &str) -> Result<String, String> {
+    let hidden = set_echo(false);
+    let answer = ask(&format!("{} (or env:NAME, file:PATH)", prompt));
+    if hidden {
+        set_echo(true);
+        println!();
+    }
+    return answer;
 }
 
-fn get_past_date(dt: Date<Tz>) -> Date<Tz> {
-    let mut year = dt.year();
-    let mut month = dt.month();
-    let tz = dt.timezone();
+fn set_echo(echo: bool) -> bool {
+    return std::process::Command::new("stty")
+        .arg(if echo { "echo" } else { "-echo" })
+        .stdin(std::process::Stdio::inherit())
+        .stderr(std::process::Stdio::null())
+        .status()
+        .map_or(false, |it| it.success());
+}
 
-    if month <= 1 {
-        month = 12;
-        year = year - 1;
-    } else {
-        month = month - 1;
+/// lists the items to choose by number. the name is typed when there is nothing to choose.
+fn choose(prompt: &str, items: &[&str]) -> Result<String, String> {
+    if items.is_empty() {
+        println!("no candidates found in Moneyforward");
+        return ask(prompt);
+    }
+    for (i, item) in items.iter().enumerate() {
+        println!("{:>3}) {}", i + 1, item);
     }
+    loop {
+        let answer = ask(&format!("{} [1-{}]", prompt, items.len()))?;
+        match answer.parse::<usize>() {
+            Ok(i) if i >= 1 && i <= items.len() => return Ok(items[i - 1].to_string()),
+            _ => println!("choose a number in the list"),
+        }
+    }
+}
+
+/// yes or no. no by default.
+fn confirm(prompt: &str) -> Result<bool, String> {
+    let answer = read_answer(&format!("{} [y/N]", prompt))?;
+    return Ok(answer.eq_ignore_ascii_case("y") || answer.eq_ignore_ascii_case("yes"));
+}
 
-    tz.ymd(year, month, 1)
+fn choose_category(prompt: &str, mfs: &mf::MfSession) -> Result<(String, String), String> {
+    let large = choose(
+        &format!("{} (large)", prompt),
+        &discover::sorted_names(mfs.categories()),
+    )?;
+    let middles = match mfs.categories().get(&large) {
+        Some(category) => discover::sorted_names(&category.subcategory),
+        None => Vec::new(),
+    };
+    let middle = choose(&format!("{} (middle)", prompt), &middles)?;
+    return Ok((large, middle));
 }
 
-fn send_skmf(
-    mfs: &mf::MfSession,
-    ska: &ureq::Agent,
-    date: Date<Tz>,
-    skmf: &SkMfConfig,
-) -> Result<(), String> {
-    let mfd = mf::get_history(&mfs, &skmf.mf_subaccount, date)?;
-    let prepaid = sk::get_sk_history(&ska, date, sk::SkDataType::PrepaidHistory)?;
-    let payment = sk::get_sk_history(&ska, date, sk::SkDataType::PaymentHistory)?;
-
-    let mut i = 0;
-
-    for it in get_skmf_diff(&mfd, prepaid, sk::SkDataType::PrepaidHistory) {
-        let datum = mf::MfAssetDatum {
-            is_transfer: false,
-            is_income: false,
-            sub_account_from: "",
-            sub_account_to: "",
-            updated_at: it.date,
-            amount: it.price as i32,
-            sub_account: &skmf.mf_subaccount,
-            content: &it.menu,
-            large_category: &skmf.mf_large_category,
-            middle_category: &skmf.mf_middle_category,
-        };
-        send_datum(&mfs, datum)?;
-        i = i + 1;
+/// asks credentials, logs in to Moneyforward and lets the names be picked from it.
+fn init_config(path: &str, cmd: &InitCommand) -> Result<(), String> {
+    if !cmd.force && std::path::Path::new(path).exists() {
+        return Err(format!(
+            "conf[{}] already exists. use --force to overwrite",
+            path
+        ));
     }
-    println!("prepaid. send {} records", i);
-    i = 0;
-
-    for it in get_skmf_diff(&mfd, payment, sk::SkDataType::PaymentHistory) {
-        let datum = mf::MfAssetDatum {
-            is_transfer: true,
-            is_income: false,
-            sub_account_from: &skmf.mf_subaccount_from,
-            sub_account_to: &skmf.mf_subaccount,
-            updated_at: it.date,
-            amount: it.price as i32,
-            sub_account: &skmf.mf_subaccount,
-            content: &it.menu,
-            large_category: &skmf.mf_charge_large_category,
-            middle_category: &skmf.mf_charge_middle_category,
-        };
-        send_datum(&mfs, datum)?;
-        i = i + 1;
+
+    let mut answers = init::InitAnswers::default();
+    answers.mf_email = ask("Moneyforward email")?;
+    answers.mf_pass = ask_secret("Moneyforward password")?;
+    answers.sk_user = ask("seikyo login id")?;
+    answers.sk_pass = ask_secret("seikyo password")?;
+
+    let user = mf::MfUser {
+        email: answers.mf_email.clone(),
+        pass: resolve_secret(&answers.mf_pass)?,
+    };
+    let mfs = mf::MfSession::login(user)?;
+
+    let targets = discover::target_names(mfs.accounts(), mfs.subaccounts());
+    answers.mf_subaccount = match targets.is_empty() {
+        true => {
+            println!(
+                "no account to register to. name a manual account to create by --create-missing"
+            );
+            ask("account to register to")?
+        }
+        false => choose("account to register to", &targets)?,
+    };
+    let from: Vec<&str> = discover::sorted_names(mfs.subaccounts())
+        .into_iter()
+        .filter(|it| *it != answers.mf_subaccount)
+        .collect();
+    answers.mf_subaccount_from = choose("account to charge from", &from)?;
+    (answers.mf_large_category, answers.mf_middle_category) =
+        choose_category("category of purchases", &mfs)?;
+    (
+        answers.mf_charge_large_category,
+        answers.mf_charge_middle_category,
+    ) = choose_category("category of charges", &mfs)?;
+
+    let text = init::format_config(&answers);
+    let conf: Config = toml::from_str(&text).map_err(|e| format!("conf broken:{}", e))?;
+    let problems = check::check_config(&conf.skmf, &conf.shops, conf.balance.as_ref(), &mfs);
+    mfs.save()?;
+
+    if !problems.is_empty() {
+        for it in &problems {
+            println!("{}", it);
+        }
+        if !confirm("write the config with these problems?")? {
+            return Err(format!(
+                "config not written. {} problems found",
+                problems.len()
+            ));
+        }
+    }
+    init::write_config(path, &text, cmd.force)?;
+    println!("config written to {}", path);
+    if !problems.is_empty() {
+        println!("run `skmf --create-missing check` to create missing names");
     }
-    println!("payment. send {} records", i);
+    return Ok(());
+}
 
-    Ok(())
+/// prints accounts or categories of Moneyforward.
+fn list_mf(path: &str, cmd: &MfCommand) -> Result<(), String> {
+    let mfs = mf::MfSession::login(load_mf_user(path)?)?;
+    let text = match &cmd.command {
+        MfSubCommand::Accounts(it) if it.toml => {
+            discover::format_accounts_toml(mfs.accounts(), mfs.subaccounts())
+        }
+        MfSubCommand::Accounts(_) => discover::format_accounts(mfs.accounts(), mfs.subaccounts()),
+        MfSubCommand::Categories(it) if it.toml => {
+            discover::format_categories_toml(mfs.categories())
+        }
+        MfSubCommand::Categories(_) => discover::format_categories(mfs.categories()),
+    };
+    mfs.save()?;
+    print!("{}", text);
+    return Ok(());
 }
 
-use std::collections::HashSet;
-fn get_skmf_diff(
-    mfdata: &Vec<mf::MfDatum>,
-    skdata: Vec<sk::SkDatum>,
-    skdtype: sk::SkDataType,
-) -> Vec<sk::SkDatum> {
-    let mut filtered = Vec::<sk::SkDatum>::new();
-
-    let mut watched = HashSet::<&String>::new();
-
-    'skloop: for it in skdata {
-        for c in mfdata {
-            let price = match skdtype {
-                sk::SkDataType::PrepaidHistory => {
-                    if c.price > 0 {
-                        continue;
+/// prints problems of the config found by the live or mock session.
+fn check_config(conf: Config, cmd: &CheckCommand, create_missing: bool) -> Result<(), String> {
+    let problems = match &cmd.mock {
+        Some(path) => {
+            let names = check::load_mock_names(path)?;
+            check::check_config(&conf.skmf, &conf.shops, conf.balance.as_ref(), &names)
+        }
+        None => {
+            let mut mfs = mf::MfSession::login(conf.mf)?;
+            if create_missing {
+                let created =
+                    check::create_missing(&conf.skmf, &conf.shops, conf.balance.as_ref(), &mut mfs);
+                match created {
+                    Ok(created) => {
+                        for it in created {
+                            println!("created {}", it);
+                        }
                     }
-                    (c.price * -1) as u32
-                }
-                sk::SkDataType::PaymentHistory => {
-                    if c.price < 0 {
-                        continue;
+                    Err(e) => {
+                        mfs.save()?;
+                        return Err(e);
                     }
-                    c.price as u32
                 }
-            };
-
-            if watched.contains(&c.id) {
-                continue;
-            }
-
-            if it.date == c.date && it.price == price && it.menu == c.content {
-                watched.insert(&c.id);
-                continue 'skloop;
             }
+            let problems =
+                check::check_config(&conf.skmf, &conf.shops, conf.balance.as_ref(), &mfs);
+            mfs.save()?;
+            problems
         }
-        filtered.push(it);
+    };
+    if !problems.is_empty() {
+        for it in &problems {
+            println!("{}", it);
+        }
+        return Err(format!("{} problems found", problems.len()));
     }
-    return filtered;
+    println!("config ok");
+    return Ok(());
 }
 
-#[cfg(test)]
-mod tests {
-    use crate::{mf::MfDatum, sk::SkDatum};
-
-    use super::*;
-
-    use sk;
-
-    #[test]
-    fn test_diff_prepaid() {
-        let mf_dummy = vec![
-            make_dummy_mfdatum(Tokyo.ymd(2022, 7, 10), "menu1", -120, "id1"),
-            make_dummy_mfdatum(Tokyo.ymd(2022, 7, 11), "menu2", -123, "id2"),
-            make_dummy_mfdatum(Tokyo.ymd(2022, 7, 11), "menu2", -123, "id3"),
-            make_dummy_mfdatum(Tokyo.ymd(2022, 7, 13), "menu2", -123, "id4"),
-            make_dummy_mfdatum(Tokyo.ymd(2022, 7, 13), "menu3", -125, "id5"),
-            make_dummy_mfdatum(Tokyo.ymd(2022, 7, 14), "menu1", -120, "id6"),
-        ];
-
-        let sk_dummy = vec![
-            make_dummy_skdatum(Tokyo.ymd(2022, 7, 10), "menu1", 120), // registered
-            make_dummy_skdatum(Tokyo.ymd(2022, 7, 11), "menu2", 123), // registered
-            make_dummy_skdatum(Tokyo.ymd(2022, 7, 11), "menu2", 123), // registered
-            make_dummy_skdatum(Tokyo.ymd(2022, 7, 11), "menu2", 123),
-            make_dummy_skdatum(Tokyo.ymd(2022, 7, 13), "menu2", 123), // registered
-            make_dummy_skdatum(Tokyo.ymd(2022, 7, 13), "menu3", 125), // registered
-            make_dummy_skdatum(Tokyo.ymd(2022, 7, 14), "menu2", 123),
-            make_dummy_skdatum(Tokyo.ymd(2022, 7, 14), "menu1", 120), // registered
-            make_dummy_skdatum(Tokyo.ymd(2022, 7, 14), "menu4", 129),
-            make_dummy_skdatum(Tokyo.ymd(2022, 7, 15), "menu4", 129),
-        ];
-
-        let sk_want_result = vec![
-            make_dummy_skdatum(Tokyo.ymd(2022, 7, 11), "menu2", 123),
-            make_dummy_skdatum(Tokyo.ymd(2022, 7, 14), "menu2", 123),
-            make_dummy_skdatum(Tokyo.ymd(2022, 7, 14), "menu4", 129),
-            make_dummy_skdatum(Tokyo.ymd(2022, 7, 15), "menu4", 129),
-        ];
-
-        let result = get_skmf_diff(&mf_dummy, sk_dummy, sk::SkDataType::PrepaidHistory);
-
-        for (i, it) in result.iter().enumerate() {
-            assert!(compare_sk(it, &sk_want_result[i]));
-        }
+/// learns item prices from the prepaid history of the last months and prints them.
+fn update_catalog(conf: Config, cmd: &CatalogCommand) -> Result<(), String> {
+    let path = match &conf.items {
+        Some(items) => items.catalog.clone(),
+        None => items::default_catalog(),
+    };
+    let mut catalog = match std::path::Path::new(&path).exists() {
+        true => items::load_catalog(&path)?,
+        false => items::Catalog::default(),
+    };
+
+    let ska = sk::SkClient::login(conf.sk)?;
+    let mut date = get_date(Tokyo);
+    let mut records = Vec::new();
+    for _ in 0..cmd.months {
+        info!("fetch {}", date.format("%Y/%m"));
+        records.extend(ska.history(
+            date,
+            sk::SkDataType::PrepaidHistory,
+            &conf.skmf.normalize,
+            conf.skmf.lenient_csv,
+        )?);
+        date = get_past_date(date);
     }
 
-    #[test]
-    fn test_diff_payment() {
-        let mf_dummy = vec![
-            make_dummy_mfdatum(Tokyo.ymd(2022, 7, 10), "", 1000, "id1"),
-            make_dummy_mfdatum(Tokyo.ymd(2022, 7, 11), "", 1000, "id2"),
-            make_dummy_mfdatum(Tokyo.ymd(2022, 7, 11), "", 1000, "id3"),
-            make_dummy_mfdatum(Tokyo.ymd(2022, 7, 13), "", 1000, "id4"),
-            make_dummy_mfdatum(Tokyo.ymd(2022, 7, 13), "", 1000, "id5"),
-            make_dummy_mfdatum(Tokyo.ymd(2022, 7, 14), "", 1000, "id6"),
-        ];
-
-        let sk_dummy = vec![
-            make_dummy_skdatum(Tokyo.ymd(2022, 7, 10), "", 1000), // registered
-            make_dummy_skdatum(Tokyo.ymd(2022, 7, 11), "", 1000), // registered
-            make_dummy_skdatum(Tokyo.ymd(2022, 7, 11), "", 1000), // registered
-            make_dummy_skdatum(Tokyo.ymd(2022, 7, 11), "", 1000),
-            make_dummy_skdatum(Tokyo.ymd(2022, 7, 13), "", 1000), // registered
-            make_dummy_skdatum(Tokyo.ymd(2022, 7, 13), "", 1000), // registered
-            make_dummy_skdatum(Tokyo.ymd(2022, 7, 14), "", 1000),
-            make_dummy_skdatum(Tokyo.ymd(2022, 7, 14), "", 1000), // registered
-            make_dummy_skdatum(Tokyo.ymd(2022, 7, 14), "", 1000),
-            make_dummy_skdatum(Tokyo.ymd(2022, 7, 15), "", 1000),
-        ];
-
-        let sk_want_result = vec![
-            make_dummy_skdatum(Tokyo.ymd(2022, 7, 11), "", 1000),
-            make_dummy_skdatum(Tokyo.ymd(2022, 7, 14), "", 1000),
-            make_dummy_skdatum(Tokyo.ymd(2022, 7, 14), "", 1000),
-            make_dummy_skdatum(Tokyo.ymd(2022, 7, 15), "", 1000),
-        ];
-
-        let result = get_skmf_diff(&mf_dummy, sk_dummy, sk::SkDataType::PaymentHistory);
-
-        for (i, it) in result.iter().enumerate() {
-            assert!(compare_sk(it, &sk_want_result[i]));
-        }
-    }
+    catalog::merge_history(&mut catalog, catalog::learn_prices(&records));
+    items::save_catalog(&path, &catalog)?;
+    info!("catalog saved:{}", path);
 
-    #[test]
-    fn get_past_date_test() {
-        let today = Tokyo.ymd(2020, 3, 1);
-        let result = get_past_date(today);
-        assert_eq!(result, Tokyo.ymd(2020, 2, 1));
+    print!("{}", catalog::format_catalog(&catalog));
+    return Ok(());
+}
 
-        let today = Tokyo.ymd(2020, 1, 1);
-        let result = get_past_date(today);
-        assert_eq!(result, Tokyo.ymd(2019, 12, 1));
+fn print_stats(conf: Config, cmd: &StatsCommand) -> Result<(), String> {
+    let to = match &cmd.to {
+        Some(to) => stats::parse_month(Tokyo, to)?,
+        None => get_date(Tokyo),
+    };
+    let from = match &cmd.from {
+        Some(from) => stats::parse_month(Tokyo, from)?,
+        None => (0..11).fold(to, |date, _| get_past_date(date)),
+    };
+    let months = stats::months_between(from, to);
+    if months.is_empty() {
+        return Err(format!("--from {} is after --to {}", from, to));
     }
 
-    fn compare_sk(i: &SkDatum, j: &SkDatum) -> bool {
-        return i.date == j.date && i.menu == j.menu && i.price == j.price && i.shop == j.shop;
+    let ska = sk::SkClient::login(conf.sk)?;
+    let mut prepaid = Vec::new();
+    let mut payment = Vec::new();
+    for date in months {
+        info!("fetch {}", date.format("%Y/%m"));
+        prepaid.extend(ska.history(
+            date,
+            sk::SkDataType::PrepaidHistory,
+            &conf.skmf.normalize,
+            conf.skmf.lenient_csv,
+        )?);
+        payment.extend(ska.history(
+            date,
+            sk::SkDataType::PaymentHistory,
+            &conf.skmf.normalize,
+            conf.skmf.lenient_csv,
+        )?);
     }
 
-    fn make_dummy_mfdatum(date: Date<Tz>, content: &str, price: i32, id: &str) -> MfDatum {
-        return MfDatum {
-            target: true,
-            date: date,
-            content: content.to_string(),
-            price: price,
-            bank: "".to_string(),
-            category: "".to_string(),
-            subcategory: "".to_string(),
-            memo: "".to_string(),
-            transfer: false,
-            id: id.to_string(),
-        };
-    }
-    fn make_dummy_skdatum(date: Date<Tz>, content: &str, price: u32) -> SkDatum {
-        return SkDatum {
-            date: date,
-            price: price,
-            shop: "".to_string(),
-            menu: content.to_string(),
-        };
-    }
+    let stats = stats::get_stats(&prepaid, &payment);
+    println!("{} - {}", from.format("%Y/%m"), to.format("%Y/%m"));
+    print!("{}", stats::format_stats(&stats, cmd.top));
+    return Ok(());
 }
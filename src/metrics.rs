@@ -0,0 +1,262 @@
+use crate::report::{RecordStatus, RunReport};
+use crate::summary::RunSummary;
+use std::fs::File;
+use std::io::Write;
+
+const LAST_SUCCESS: &str = "skmf_last_success_timestamp_seconds";
+
+/// writes metrics for node_exporter's textfile collector.
+///
+/// the last success timestamp is carried over from the previous file when this run failed,
+/// and is 0 when no run has succeeded yet.
+pub fn save_metrics(
+    path: &str,
+    report: &RunReport,
+    summary: &RunSummary,
+    now: i64,
+) -> Result<(), String> {
+    let last_success = if summary.is_success() {
+        now
+    } else {
+        std::fs::read_to_string(path)
+            .ok()
+            .and_then(|data| get_last_success(&data))
+            .unwrap_or(0)
+    };
+
+    let text = format_metrics(report, summary, now, last_success);
+
+    let tmp = format!("{}.tmp", path);
+    {
+        let mut file =
+            File::create(&tmp).map_err(|e| format!("metrics[{}] create err:{}", tmp, e))?;
+        file.write_all(text.as_bytes())
+            .map_err(|e| format!("metrics[{}] write err:{}", tmp, e))?;
+    }
+    // textfile collector may read the file while writing.
+    std::fs::rename(&tmp, path).map_err(|e| format!("metrics[{}] rename err:{}", path, e))?;
+
+    return Ok(());
+}
+
+fn get_last_success(data: &str) -> Option<i64> {
+    data.lines()
+        .find_map(|line| line.strip_prefix(LAST_SUCCESS)?.trim().parse::<i64>().ok())
+}
+
+fn format_metrics(report: &RunReport, summary: &RunSummary, now: i64, last_success: i64) -> String {
+    let mut text = String::new();
+
+    push_help(
+        &mut text,
+        LAST_SUCCESS,
+        "Unix time of the last successful sync. 0 if never succeeded.",
+    );
+    text.push_str(&format!("{} {}\n", LAST_SUCCESS, last_success));
+
+    push_help(
+        &mut text,
+        "skmf_last_run_timestamp_seconds",
+        "Unix time of the last sync.",
+    );
+    text.push_str(&format!("skmf_last_run_timestamp_seconds {}\n", now));
+
+    push_help(
+        &mut text,
+        "skmf_last_run_success",
+        "1 if the last sync succeeded.",
+    );
+    text.push_str(&format!(
+        "skmf_last_run_success {}\n",
+        if summary.is_success() { 1 } else { 0 }
+    ));
+
+    let kinds = ["prepaid", "payment"];
+    let stats: Vec<_> = kinds
+        .iter()
+        .map(|kind| get_kind_stat(report, kind))
+        .collect();
+
+    let series: [(&str, &str, fn(&KindStat) -> i64); 4] = [
+        (
+            "skmf_records_fetched",
+            "Seikyo records fetched in the last sync. a record split into items counts once.",
+            |s| s.fetched as i64,
+        ),
+        (
+            "skmf_fetched_yen",
+            "Total yen of Seikyo records fetched in the last sync.",
+            |s| s.fetched_yen,
+        ),
+        (
+            "skmf_records_sent",
+            "Records sent to Moneyforward in the last sync.",
+            |s| s.sent as i64,
+        ),
+        (
+            "skmf_sent_yen",
+            "Total yen of records sent to Moneyforward in the last sync.",
+            |s| s.sent_yen,
+        ),
+    ];
+    for (name, help, value) in series.iter() {
+        push_help(&mut text, name, help);
+        for (kind, stat) in kinds.iter().zip(stats.iter()) {
+            text.push_str(&format!("{}{{type=\"{}\"}} {}\n", name, kind, value(stat)));
+        }
+    }
+
+    if let Some(delta) = summary.balance_delta {
+        push_help(
+            &mut text,
+            "skmf_balance_delta_yen",
+            "Seikyo balance minus Moneyforward balance.",
+        );
+        text.push_str(&format!("skmf_balance_delta_yen {}\n", delta));
+    }
+
+    push_help(
+        &mut text,
+        "skmf_duration_seconds",
+        "Duration of each phase of the last sync.",
+    );
+    text.push_str(&format!(
+        "skmf_duration_seconds{{phase=\"login\"}} {:.3}\n",
+        report.login_seconds
+    ));
+    text.push_str(&format!(
+        "skmf_duration_seconds{{phase=\"fetch\"}} {:.3}\n",
+        report.fetch_seconds
+    ));
+
+    return text;
+}
+
+#[derive(Default)]
+struct KindStat {
+    fetched: u32,
+    fetched_yen: i64,
+    sent: u32,
+    sent_yen: i64,
+}
+
+fn get_kind_stat(report: &RunReport, kind: &str) -> KindStat {
+    let mut stat = KindStat::default();
+    for it in report.months.iter().flat_map(|month| month.records.iter()) {
+        if it.kind != kind {
+            continue;
+        }
+        // the items of a split record add up to its price.
+        if it.item.map_or(true, |i| i == 0) {
+            stat.fetched += 1;
+        }
+        stat.fetched_yen += it.price as i64;
+        if it.status == RecordStatus::Sent {
+            stat.sent += 1;
+            stat.sent_yen += it.price as i64;
+        }
+    }
+    return stat;
+}
+
+fn push_help(text: &mut String, name: &str, help: &str) {
+    text.push_str(&format!(
+        "# HELP {} {}\n# TYPE {} gauge\n",
+        name, help, name
+    ));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sk::make_datum;
+    use crate::sk::SkDataType;
+    use chrono::TimeZone;
+    use chrono_tz::Asia::Tokyo;
+
+    #[test]
+    fn format_metrics_test() {
+        let mut report = RunReport::default();
+        report.login_seconds = 1.5;
+        report.fetch_seconds = 0.25;
+        let month = report.month(Tokyo.ymd(2022, 7, 1));
+        month.add(
+            SkDataType::PrepaidHistory,
+            &make_datum(Tokyo.ymd(2022, 7, 5), "", "", 473),
+            None,
+            None,
+            None,
+        );
+        month.add(
+            SkDataType::PrepaidHistory,
+            &make_datum(Tokyo.ymd(2022, 7, 5), "", "", 407),
+            None,
+            None,
+            None,
+        );
+        month.add(
+            SkDataType::PaymentHistory,
+            &make_datum(Tokyo.ymd(2022, 7, 5), "", "", 1000),
+            None,
+            None,
+            None,
+        );
+        month.mark(
+            SkDataType::PrepaidHistory,
+            &make_datum(Tokyo.ymd(2022, 7, 5), "", "", 407),
+            RecordStatus::Missing,
+            RecordStatus::Sent,
+        );
+
+        let mut summary = RunSummary::default();
+        summary.balance_delta = Some(-11);
+
+        let text = format_metrics(&report, &summary, 1658000000, 1658000000);
+
+        assert!(text.contains("\nskmf_last_success_timestamp_seconds 1658000000\n"));
+        assert!(text.contains("\nskmf_last_run_success 1\n"));
+        assert!(text.contains("\nskmf_records_fetched{type=\"prepaid\"} 2\n"));
+        assert!(text.contains("\nskmf_records_fetched{type=\"payment\"} 1\n"));
+        assert!(text.contains("\nskmf_fetched_yen{type=\"prepaid\"} 880\n"));
+        assert!(text.contains("\nskmf_records_sent{type=\"prepaid\"} 1\n"));
+        assert!(text.contains("\nskmf_sent_yen{type=\"prepaid\"} 407\n"));
+        assert!(text.contains("\nskmf_sent_yen{type=\"payment\"} 0\n"));
+        assert!(text.contains("\nskmf_balance_delta_yen -11\n"));
+        assert!(text.contains("\nskmf_duration_seconds{phase=\"login\"} 1.500\n"));
+        assert!(text.contains("\nskmf_duration_seconds{phase=\"fetch\"} 0.250\n"));
+
+        assert_eq!(get_last_success(&text), Some(1658000000));
+    }
+
+    #[test]
+    fn split_items_test() {
+        let mut report = RunReport::default();
+        let month = report.month(Tokyo.ymd(2022, 7, 1));
+        for (index, price) in [(0, 407), (1, 66)] {
+            let item = crate::sk::SkDatum {
+                item_of: Some(crate::sk::ItemOf {
+                    marker: "skmf:0123abcdef45".to_string(),
+                    index,
+                }),
+                ..make_datum(Tokyo.ymd(2022, 7, 5), "", "", price)
+            };
+            month.add(SkDataType::PrepaidHistory, &item, None, None, None);
+        }
+
+        let text = format_metrics(&report, &RunSummary::default(), 1658000000, 0);
+        assert!(text.contains("\nskmf_records_fetched{type=\"prepaid\"} 1\n"));
+        assert!(text.contains("\nskmf_fetched_yen{type=\"prepaid\"} 473\n"));
+    }
+
+    #[test]
+    fn failed_run_test() {
+        let report = RunReport::default();
+        let mut summary = RunSummary::default();
+        summary.errors.push("login failure!".to_string());
+
+        let text = format_metrics(&report, &summary, 1658000000, 0);
+        assert!(text.contains("\nskmf_last_success_timestamp_seconds 0\n"));
+        assert!(text.contains("\nskmf_last_run_success 0\n"));
+        assert_eq!(get_last_success(&text), Some(0));
+    }
+}
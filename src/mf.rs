@@ -1,10 +1,39 @@
+//! client of Moneyforward ME (https://moneyforward.com/).
+//!
+//! ```no_run
+//! use skmf::mf::{MfAssetDatum, MfSession, MfUser};
+//!
+//! let user = MfUser { email: "me@example.com".to_string(), pass: "pass".to_string() };
+//! let session = MfSession::login(user)?;
+//! for name in session.subaccounts().keys() {
+//!     println!("{}", name);
+//! }
+//! session.send(&MfAssetDatum {
+//!     is_transfer: false,
+//!     is_income: false,
+//!     sub_account_from: String::new(),
+//!     sub_account_to: String::new(),
+//!     updated_at: skmf::sync::get_today(chrono_tz::Asia::Tokyo),
+//!     amount: 473,
+//!     sub_account: "大学生協".to_string(),
+//!     content: "チキンカツカレーM".to_string(),
+//!     memo: String::new(),
+//!     large_category: "食費".to_string(),
+//!     middle_category: "外食".to_string(),
+//! })?;
+//! session.save()?;
+//! # Ok::<(), String>(())
+//! ```
+use crate::logging::{redact_form, redact_url};
+use crate::normalize::{normalize, NormalizeConfig};
 use chrono::Datelike;
 use cookie_store::CookieStore;
+use log::debug;
 use parsercher;
 use parsercher::dom::Dom;
 use parsercher::dom::DomType;
 use parsercher::dom::Tag;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::fs::File;
 use std::io::BufReader;
 use std::io::BufWriter;
@@ -15,12 +44,17 @@ use chrono::Date;
 use chrono::TimeZone;
 use chrono_tz::{Asia::Tokyo, Tz};
 
+/// login credentials of Moneyforward ME.
 #[derive(serde_derive::Deserialize, Debug)]
 pub struct MfUser {
     pub email: String,
     pub pass: String,
 }
 
+/// a logged-in session of Moneyforward ME.
+///
+/// cookies are kept in "cookies.json" of the current directory to avoid the login
+/// notification mail on every run.
 #[derive(Debug)]
 pub struct MfSession {
     agent: ureq::Agent,
@@ -28,9 +62,77 @@ pub struct MfSession {
     accounts: HashMap<String, String>,
     subaccounts: HashMap<String, String>,
     categories: HashMap<String, MfAccountCategory>,
+    balances: HashMap<String, i64>,
 }
 
-pub fn save_mf_session(session: MfSession) -> Result<(), String> {
+impl MfSession {
+    /// restores the saved session, or logs in when it has expired.
+    pub fn login(user: MfUser) -> Result<MfSession, String> {
+        return get_mf_session(user);
+    }
+
+    /// saves cookies of the session for the next login.
+    pub fn save(self) -> Result<(), String> {
+        return save_mf_session(self);
+    }
+
+    /// account names and their id hashes.
+    pub fn accounts(&self) -> &HashMap<String, String> {
+        return &self.accounts;
+    }
+
+    /// subaccount names, which entries are registered to, and their id hashes.
+    pub fn subaccounts(&self) -> &HashMap<String, String> {
+        return &self.subaccounts;
+    }
+
+    /// large categories by name.
+    pub fn categories(&self) -> &HashMap<String, MfAccountCategory> {
+        return &self.categories;
+    }
+
+    /// fetches entries of the account in the month of `date`.
+    pub fn history(
+        &self,
+        account: &str,
+        date: Date<Tz>,
+        norm: &NormalizeConfig,
+    ) -> Result<Vec<MfDatum>, String> {
+        return get_history(self, account, date, norm);
+    }
+
+    /// registers an entry.
+    pub fn send(&self, datum: &MfAssetDatum) -> Result<(), String> {
+        return send_datum(self, datum);
+    }
+
+    /// refetches balances of accounts. the balances at login become stale after sending data.
+    pub fn refresh_balances(&mut self) -> Result<(), String> {
+        return refresh_balances(self);
+    }
+
+    /// balance of the account as of the login or the last refresh.
+    pub fn balance(&self, account: &str) -> Result<i64, String> {
+        return get_balance(self, account);
+    }
+
+    /// refetches accounts, categories and balances, e.g. after creating some of them.
+    pub fn refresh(&mut self) -> Result<(), String> {
+        return refresh_session(self);
+    }
+
+    /// creates a middle category under the large category. refresh to see it.
+    pub fn create_middle_category(&self, large: &str, middle: &str) -> Result<(), String> {
+        return create_middle_category(self, large, middle);
+    }
+
+    /// creates a manual account of electronic money / prepaid. refresh to see it.
+    pub fn create_manual_account(&self, name: &str) -> Result<(), String> {
+        return create_manual_account(self, name);
+    }
+}
+
+fn save_mf_session(session: MfSession) -> Result<(), String> {
     let mut file = BufWriter::new(File::create("cookies.json").map_err(|e| e.to_string())?);
     session
         .agent
@@ -41,7 +143,7 @@ pub fn save_mf_session(session: MfSession) -> Result<(), String> {
     return Ok(());
 }
 
-pub fn get_mf_session(user: MfUser) -> Result<MfSession, String> {
+fn get_mf_session(user: MfUser) -> Result<MfSession, String> {
     let store = match File::open("cookies.json") {
         Ok(f) => {
             let file = BufReader::new(f);
@@ -64,6 +166,7 @@ pub fn get_mf_session(user: MfUser) -> Result<MfSession, String> {
         .cookie_store(store)
         .build();
     // */
+    debug!("GET https://moneyforward.com/");
     let res = agent
         .get("https://moneyforward.com/")
         .call()
@@ -79,14 +182,19 @@ pub fn get_mf_session(user: MfUser) -> Result<MfSession, String> {
                 accounts: get_accounts(&root_dom)?,
                 subaccounts: get_subaccounts(&root_dom)?,
                 categories: get_account_types(&root_dom)?,
+                balances: get_balances(&root_dom)?,
             });
         }
-        None => create_mf_session(agent, user),
+        None => {
+            debug!("session expired. login as {}", user.email);
+            create_mf_session(agent, user)
+        }
     }
 }
 
 fn create_mf_session(agent: ureq::Agent, user: MfUser) -> Result<MfSession, String> {
     // get client info
+    debug!("GET https://moneyforward.com/sign_in");
     let res = agent
         .get("https://moneyforward.com/sign_in")
         .call()
@@ -96,6 +204,7 @@ fn create_mf_session(agent: ureq::Agent, user: MfUser) -> Result<MfSession, Stri
     let login = get_login_url(res.get_url())?;
 
     // get email login form
+    debug!("GET {}", redact_url(login.as_str()));
     let res = agent
         .get(login.as_str())
         .call()
@@ -121,6 +230,10 @@ fn create_mf_session(agent: ureq::Agent, user: MfUser) -> Result<MfSession, Stri
     let queries: Vec<_> = queries.iter().map(|(k, v)| (*k, *v)).collect();
 
     // set email mode
+    debug!(
+        "POST https://id.moneyforward.com/sign_in/email {}",
+        redact_form(&queries)
+    );
     let res = agent
         .post("https://id.moneyforward.com/sign_in/email")
         .send_form(&queries[..])
@@ -144,6 +257,10 @@ fn create_mf_session(agent: ureq::Agent, user: MfUser) -> Result<MfSession, Stri
     queries.insert("mfid_user[password]", user.pass.as_str());
 
     let queries: Vec<_> = queries.iter().map(|(k, v)| (*k, *v)).collect();
+    debug!(
+        "POST https://id.moneyforward.com/sign_in {}",
+        redact_form(&queries)
+    );
     let res = agent
         .post("https://id.moneyforward.com/sign_in")
         .send_form(&queries[..])
@@ -159,6 +276,7 @@ fn create_mf_session(agent: ureq::Agent, user: MfUser) -> Result<MfSession, Stri
         accounts: get_accounts(&root_dom)?,
         subaccounts: get_subaccounts(&root_dom)?,
         categories: get_account_types(&root_dom)?,
+        balances: get_balances(&root_dom)?,
     });
 }
 
@@ -240,11 +358,13 @@ fn get_subaccounts(root_dom: &Dom) -> Result<HashMap<String, String>, String> {
     return Err("falure to fetch subaccounts".to_string());
 }
 
+/// a large category and its middle categories.
 #[derive(Debug)]
-struct MfAccountCategory {
-    name: String,
-    id: String,
-    subcategory: HashMap<String, String>,
+pub struct MfAccountCategory {
+    pub name: String,
+    pub id: String,
+    /// middle category names and their ids
+    pub subcategory: HashMap<String, String>,
 }
 
 fn get_account_types(root_dom: &Dom) -> Result<HashMap<String, MfAccountCategory>, &str> {
@@ -364,6 +484,7 @@ fn get_subcategory(dom: &Dom) -> Result<HashMap<String, String>, &'static str> {
     return Err("dame");
 }
 
+/// an entry of the Moneyforward csv.
 #[derive(Debug)]
 pub struct MfDatum {
     pub target: bool,
@@ -376,12 +497,127 @@ pub struct MfDatum {
     pub memo: String,
     pub transfer: bool,
     pub id: String,
+    /// columns unknown to skmf keyed by the header name
+    pub extra: BTreeMap<String, String>,
+}
+
+struct MfColumns {
+    target: Option<usize>,
+    date: usize,
+    content: usize,
+    price: usize,
+    bank: Option<usize>,
+    category: Option<usize>,
+    subcategory: Option<usize>,
+    memo: Option<usize>,
+    transfer: Option<usize>,
+    id: usize,
+    extra: Vec<(usize, String)>,
+}
+
+fn get_columns(headers: &csv::StringRecord) -> Result<MfColumns, String> {
+    let names: Vec<String> = headers
+        .iter()
+        .map(|it| it.trim_start_matches('\u{feff}').trim().to_string())
+        .collect();
+    let mut used = vec![false; names.len()];
+    // "金額（円）" is matched by "金額".
+    let mut find = |name: &str| {
+        let i = names.iter().position(|it| {
+            it == name
+                || it.starts_with(&format!("{}（", name))
+                || it.starts_with(&format!("{}(", name))
+        })?;
+        used[i] = true;
+        Some(i)
+    };
+
+    let target = find("計算対象");
+    let date = find("日付");
+    let content = find("内容");
+    let price = find("金額");
+    let bank = find("保有金融機関");
+    let category = find("大項目");
+    let subcategory = find("中項目");
+    let memo = find("メモ");
+    let transfer = find("振替");
+    let id = find("ID");
+
+    let require = |i: Option<usize>, name: &str| i.ok_or(format!("column [{}] not found", name));
+    return Ok(MfColumns {
+        target,
+        date: require(date, "日付")?,
+        content: require(content, "内容")?,
+        price: require(price, "金額")?,
+        bank,
+        category,
+        subcategory,
+        memo,
+        transfer,
+        id: require(id, "ID")?,
+        extra: names
+            .into_iter()
+            .enumerate()
+            .filter(|(i, _)| !used[*i])
+            .collect(),
+    });
+}
+
+fn parse_mf_record(
+    record: &csv::StringRecord,
+    columns: &MfColumns,
+    norm: &NormalizeConfig,
+) -> Result<MfDatum, String> {
+    let field = |i: usize| record.get(i).ok_or(format!("column {} missing", i + 1));
+    let optional = |i: Option<usize>| i.and_then(|i| record.get(i)).unwrap_or("");
+
+    return Ok(MfDatum {
+        target: optional(columns.target).eq("1"),
+        date: parse_date(field(columns.date)?)?,
+        content: normalize(norm, field(columns.content)?),
+        price: field(columns.price)?
+            .parse::<i32>()
+            .map_err(|e| format!("invalid price data type:{}", e.to_string()))?,
+        bank: optional(columns.bank).to_string(),
+        category: optional(columns.category).to_string(),
+        subcategory: optional(columns.subcategory).to_string(),
+        memo: optional(columns.memo).to_string(),
+        transfer: optional(columns.transfer).eq("1"),
+        id: field(columns.id)?.to_string(),
+        extra: columns
+            .extra
+            .iter()
+            .map(|(i, name)| (name.clone(), record.get(*i).unwrap_or("").to_string()))
+            .collect(),
+    });
+}
+
+/// parses the csv of Moneyforward history. columns are looked up by the header names.
+pub fn parse_mf_csv(rdr: impl Read, norm: &NormalizeConfig) -> Result<Vec<MfDatum>, String> {
+    let mut reader = csv::ReaderBuilder::new().flexible(true).from_reader(rdr);
+    let headers = reader
+        .headers()
+        .map_err(|e| format!("csv header broken:{}", e))?
+        .clone();
+    let columns = get_columns(&headers)?;
+
+    let mut data = Vec::<MfDatum>::new();
+    for record in reader.records() {
+        let record = record.map_err(|e| format!("csv data broken:{}", e))?;
+        let it = parse_mf_record(&record, &columns, norm).map_err(|e| {
+            let line = record.position().map_or(0, |p| p.line());
+            format!("csv line {}: {}", line, e)
+        })?;
+        data.push(it);
+    }
+    return Ok(data);
 }
 
-pub fn get_history(
+fn get_history(
     session: &MfSession,
     account: &str,
     date: Date<Tz>,
+    norm: &NormalizeConfig,
 ) -> Result<Vec<MfDatum>, String> {
     let account_id_hash = session
         .accounts
@@ -395,6 +631,7 @@ pub fn get_history(
         date.month()
     );
 
+    debug!("GET {}", redact_url(&url));
     let result = session
         .agent
         .get(&url)
@@ -405,48 +642,184 @@ pub fn get_history(
         return Err("invalid data type".to_string());
     }
 
-    let mut data = Vec::<MfDatum>::new();
+    let charset = result.header("Content-Type").and_then(get_charset);
+    let charset = charset.map(|it| it.to_string());
+    let content_length = result.header("Content-Length").map(|it| it.to_string());
+    let content_encoding = result.header("Content-Encoding").map(|it| it.to_string());
+    let body = read_body(
+        result.into_reader(),
+        content_length.as_deref(),
+        content_encoding.as_deref(),
+    )?;
+    let prefix = &body[..body.len().min(SNIFF_LENGTH)];
+    let encoding = detect_encoding(prefix, charset.as_deref(), "Shift_JIS")?;
+    debug!(
+        "mf csv encoding:{} (declared:{:?})",
+        encoding.name(),
+        charset
+    );
 
-    // server returns with false charset.
-    let body = get_encoded_string(&mut result.into_reader(), "Shift_JIS")?;
+    let mut decoder = DecodeReader::new(std::io::Cursor::new(body), encoding);
+    return parse_mf_csv(&mut decoder, norm);
+}
 
-    let enc = kana::half2full(&body);
-    let mut reader = csv::Reader::from_reader(enc.as_bytes());
-    for record in reader.records() {
-        let record = record.map_err(|e| format!("csv data broken:{}", e))?;
-        let it = MfDatum {
-            target: record[0].eq("1"),
-            date: parse_date(&record[1])?,
-            content: record[2].to_string(),
-            price: record[3]
-                .parse::<i32>()
-                .map_err(|e| format!("invalid price data type:{}", e.to_string()))?,
-            bank: record[4].to_string(),
-            category: record[5].to_string(),
-            subcategory: record[6].to_string(),
-            memo: record[7].to_string(),
-            transfer: record[8].eq("1"),
-            id: record[9].to_string(),
-        };
-        data.push(it);
+use encoding_rs::{DecoderResult, Encoding};
+use std::io::Read;
+
+/// bytes read ahead to detect the charset. the header line of the csv fits in it.
+const SNIFF_LENGTH: usize = 4096;
+
+/// reads the whole body. a body shorter than Content-Length is a broken download, not a month
+/// with fewer entries. the length is of the encoded body when Content-Encoding is given.
+fn read_body(
+    mut rdr: impl Read,
+    content_length: Option<&str>,
+    content_encoding: Option<&str>,
+) -> Result<Vec<u8>, String> {
+    let mut body = Vec::new();
+    rdr.read_to_end(&mut body)
+        .map_err(|e| format!("failure to read mf csv:{}", e))?;
+
+    let identity = content_encoding.map_or(true, |it| it.trim().eq_ignore_ascii_case("identity"));
+    if let (true, Some(length)) = (identity, content_length) {
+        let length: usize = length
+            .trim()
+            .parse()
+            .map_err(|e| format!("invalid Content-Length [{}]:{}", length, e))?;
+        if body.len() != length {
+            return Err(format!(
+                "mf csv truncated: {} of {} bytes",
+                body.len(),
+                length
+            ));
+        }
     }
+    return Ok(body);
+}
 
-    return Ok(data);
+/// the charset parameter of the Content-Type header.
+fn get_charset(content_type: &str) -> Option<&str> {
+    return content_type.split(';').skip(1).find_map(|it| {
+        let (key, value) = it.split_once('=')?;
+        if !key.trim().eq_ignore_ascii_case("charset") {
+            return None;
+        }
+        Some(value.trim().trim_matches('"'))
+    });
 }
 
-use encoding_rs::Encoding;
-use std::io::Read;
-fn get_encoded_string(rdr: &mut impl std::io::Read, charset: &str) -> Result<String, String> {
-    let mut buf: Vec<u8> = vec![];
-    let encoding = Encoding::for_label(charset.as_bytes()).ok_or("unknown encoding")?;
+/// whether the bytes are valid in the encoding. a character may be cut at the end.
+fn decodes_as(encoding: &'static Encoding, prefix: &[u8]) -> bool {
+    let mut decoder = encoding.new_decoder_without_bom_handling();
+    let len = match decoder.max_utf8_buffer_length_without_replacement(prefix.len()) {
+        Some(len) => len,
+        None => return false,
+    };
+    let mut output = String::with_capacity(len);
+    let (result, _) = decoder.decode_to_string_without_replacement(prefix, &mut output, false);
+    return !matches!(result, DecoderResult::Malformed(_, _));
+}
+
+/// detects the charset by BOM, the declared charset if the first bytes are valid in it,
+/// or UTF-8 validity of the first bytes. `fallback` otherwise.
+fn detect_encoding(
+    prefix: &[u8],
+    declared: Option<&str>,
+    fallback: &str,
+) -> Result<&'static Encoding, String> {
+    if let Some((encoding, _)) = Encoding::for_bom(prefix) {
+        return Ok(encoding);
+    }
+    // server may return with false charset.
+    if let Some(encoding) = declared.and_then(|it| Encoding::for_label(it.as_bytes())) {
+        if decodes_as(encoding, prefix) {
+            return Ok(encoding);
+        }
+    }
+    let is_utf8 = match std::str::from_utf8(prefix) {
+        Ok(text) => !text.is_ascii(),
+        // a character may be cut at the end of the prefix.
+        Err(e) => e.error_len().is_none() && e.valid_up_to() > 0,
+    };
+    if is_utf8 {
+        return Ok(encoding_rs::UTF_8);
+    }
+    return Encoding::for_label(fallback.as_bytes())
+        .ok_or(format!("unknown encoding:{}", fallback));
+}
 
-    let _ = rdr
-        .take(4194304)
-        .read_to_end(&mut buf)
-        .map_err(|e| format!("ioerr:{:?}", e.to_string()));
-    let (text, _, _) = encoding.decode(&buf);
+/// decodes the stream into UTF-8 while reading.
+/// malformed or truncated input is an error instead of a replacement character.
+struct DecodeReader<R> {
+    inner: R,
+    decoder: encoding_rs::Decoder,
+    input: Vec<u8>,
+    output: Vec<u8>,
+    pos: usize,
+    eof: bool,
+    /// bytes read from `inner`
+    count: u64,
+}
 
-    return Ok(text.into_owned());
+impl<R: Read> DecodeReader<R> {
+    fn new(inner: R, encoding: &'static Encoding) -> Self {
+        DecodeReader {
+            inner,
+            decoder: encoding.new_decoder_with_bom_removal(),
+            input: vec![0; 8192],
+            output: Vec::new(),
+            pos: 0,
+            eof: false,
+            count: 0,
+        }
+    }
+}
+
+impl<R: Read> Read for DecodeReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        while self.pos >= self.output.len() {
+            if self.eof {
+                return Ok(0);
+            }
+            let n = self.inner.read(&mut self.input)?;
+            self.count += n as u64;
+            self.eof = n == 0;
+
+            let len = self
+                .decoder
+                .max_utf8_buffer_length_without_replacement(n)
+                .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::Other, "buffer overflow"))?;
+            self.output.resize(len, 0);
+            self.pos = 0;
+            let (result, _, written) = self.decoder.decode_to_utf8_without_replacement(
+                &self.input[..n],
+                &mut self.output,
+                self.eof,
+            );
+            self.output.truncate(written);
+            if let DecoderResult::Malformed(_, _) = result {
+                let msg = if self.eof {
+                    format!(
+                        "truncated {} at byte {}",
+                        self.decoder.encoding().name(),
+                        self.count
+                    )
+                } else {
+                    format!(
+                        "malformed {} near byte {}",
+                        self.decoder.encoding().name(),
+                        self.count
+                    )
+                };
+                return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, msg));
+            }
+        }
+
+        let n = std::cmp::min(buf.len(), self.output.len() - self.pos);
+        buf[..n].copy_from_slice(&self.output[self.pos..self.pos + n]);
+        self.pos += n;
+        return Ok(n);
+    }
 }
 
 fn parse_date(date: &str) -> Result<Date<Tz>, String> {
@@ -470,50 +843,59 @@ fn parse_date(date: &str) -> Result<Date<Tz>, String> {
         .parse::<u32>()
         .map_err(|e| format!("invaid day data:{:?}", e.to_string()))?;
 
-    let dt = Tokyo.ymd(year, month, day);
+    let dt = Tokyo
+        .ymd_opt(year, month, day)
+        .single()
+        .ok_or(format!("invalid date:{}", date))?;
 
     return Ok(dt);
 }
 
-#[derive(Debug)]
-pub struct MfAssetDatum<'a> {
+/// an entry to register.
+#[derive(Debug, Clone)]
+pub struct MfAssetDatum {
     ///振替
     pub is_transfer: bool,
     /// 収入
     pub is_income: bool,
     /// 振替時の出金元
-    pub sub_account_from: &'a str,
+    pub sub_account_from: String,
     /// 振替時の入金先
-    pub sub_account_to: &'a str,
+    pub sub_account_to: String,
     /// 更新日時
     pub updated_at: Date<Tz>,
     /// 金額
     pub amount: i32,
     /// 出金対象
-    pub sub_account: &'a str,
+    pub sub_account: String,
     /// 内容
-    pub content: &'a str,
+    pub content: String,
+    /// メモ
+    pub memo: String,
     /// 大分類
-    pub large_category: &'a str,
+    pub large_category: String,
     /// 中分類
-    pub middle_category: &'a str,
+    pub middle_category: String,
 }
 
-pub fn send_datum(session: &MfSession, datum: MfAssetDatum) -> Result<(), String> {
+fn send_datum(session: &MfSession, datum: &MfAssetDatum) -> Result<(), String> {
     let mut formdatum = Vec::new();
 
     let updated_at = datum.updated_at.format("%Y/%m/%d").to_string();
     let amount = format!("{}", datum.amount);
 
-    let category = session.categories.get(datum.large_category).ok_or(format!(
-        "large category [{}] not found",
-        datum.large_category
-    ))?;
+    let category = session
+        .categories
+        .get(&datum.large_category)
+        .ok_or(format!(
+            "large category [{}] not found",
+            datum.large_category
+        ))?;
 
     let large_category_id = &category.id;
     let middle_category_id = category
         .subcategory
-        .get(datum.middle_category)
+        .get(&datum.middle_category)
         .ok_or(format!(
             "middle account [{}] not found",
             datum.middle_category
@@ -521,13 +903,13 @@ pub fn send_datum(session: &MfSession, datum: MfAssetDatum) -> Result<(), String
 
     let sub_account_id_hash = session
         .subaccounts
-        .get(datum.sub_account)
+        .get(&datum.sub_account)
         .ok_or(format!("sub account [{}] not found", datum.sub_account))?;
 
     let sub_account_id_hash_from = if datum.sub_account_from != "" {
         session
             .subaccounts
-            .get(datum.sub_account_from)
+            .get(&datum.sub_account_from)
             .ok_or(format!(
                 "subaccount from[{}] not found",
                 datum.sub_account_from
@@ -538,7 +920,7 @@ pub fn send_datum(session: &MfSession, datum: MfAssetDatum) -> Result<(), String
     let sub_account_id_hash_to = if datum.sub_account_to != "" {
         session
             .subaccounts
-            .get(datum.sub_account_to)
+            .get(&datum.sub_account_to)
             .ok_or(format!("subaccount to[{}] not found", datum.sub_account_to))?
     } else {
         ""
@@ -569,7 +951,14 @@ pub fn send_datum(session: &MfSession, datum: MfAssetDatum) -> Result<(), String
     formdatum.push(("user_asset_act[large_category_id]", large_category_id));
     formdatum.push(("user_asset_act[middle_category_id]", middle_category_id));
     formdatum.push(("user_asset_act[content]", &datum.content));
+    if datum.memo != "" {
+        formdatum.push(("user_asset_act[memo]", &datum.memo));
+    }
 
+    debug!(
+        "POST https://moneyforward.com/user_asset_acts {}",
+        redact_form(&formdatum)
+    );
     let _ = session
         .agent
         .post("https://moneyforward.com/user_asset_acts")
@@ -629,6 +1018,344 @@ fn get_accounts(root_dom: &Dom) -> Result<HashMap<String, String>, String> {
     return Ok(accounts);
 }
 
+fn get_balances(root_dom: &Dom) -> Result<HashMap<String, i64>, String> {
+    let mut needle_tag = Tag::new("li");
+    needle_tag.set_attr("class", "account facilities-column border-bottom-dotted");
+    let mut needle = parsercher::dom::Dom::new(parsercher::dom::DomType::Tag);
+    needle.set_tag(needle_tag);
+
+    let account_doms = parsercher::search_dom(&root_dom, &needle).ok_or("account not found")?;
+
+    let mut balances = HashMap::new();
+
+    for account in account_doms
+        .get_children()
+        .ok_or("account children not found")?
+    {
+        let mut needle_tag = Tag::new("p");
+        needle_tag.set_attr("class", "heading-accounts");
+        let mut needle = parsercher::dom::Dom::new(parsercher::dom::DomType::Tag);
+        needle.set_tag(needle_tag);
+        let name = match parsercher::search_dom(&account, &needle) {
+            Some(heading) => get_first_text(&heading)?,
+            None => continue,
+        };
+
+        let mut needle_tag = Tag::new("li");
+        needle_tag.set_attr("class", "number");
+        let mut needle = parsercher::dom::Dom::new(parsercher::dom::DomType::Tag);
+        needle.set_tag(needle_tag);
+        let number = match parsercher::search_dom(&account, &needle) {
+            Some(number) => get_first_text(&number)?,
+            None => continue,
+        };
+
+        balances.insert(name, parse_yen(&number)?);
+    }
+
+    return Ok(balances);
+}
+
+fn get_first_text(dom: &Dom) -> Result<String, String> {
+    if let Some(text) = dom.get_text() {
+        let text = text.get_text().trim();
+        if text != "" {
+            return Ok(text.to_string());
+        }
+    }
+    for child in dom.get_children().ok_or("text not found")? {
+        if let Ok(text) = get_first_text(child) {
+            return Ok(text);
+        }
+    }
+    return Err("text not found".to_string());
+}
+
+fn parse_yen(text: &str) -> Result<i64, String> {
+    // "-1,234円"
+    let number = text.trim().trim_end_matches("円").replace(",", "");
+    return number
+        .parse::<i64>()
+        .map_err(|e| format!("invalid yen value[{}]:{}", text, e));
+}
+
+fn refresh_balances(session: &mut MfSession) -> Result<(), String> {
+    debug!("GET https://moneyforward.com/");
+    let res = session
+        .agent
+        .get("https://moneyforward.com/")
+        .call()
+        .map_err(|e| e.to_string())?;
+    let html = res.into_string().map_err(|e| e.to_string())?;
+    let root_dom = parsercher::parse(&html).map_err(|e| e.to_string())?;
+
+    session.balances = get_balances(&root_dom)?;
+
+    return Ok(());
+}
+
+fn refresh_session(session: &mut MfSession) -> Result<(), String> {
+    debug!("GET https://moneyforward.com/");
+    let res = session
+        .agent
+        .get("https://moneyforward.com/")
+        .call()
+        .map_err(|e| e.to_string())?;
+    let html = res.into_string().map_err(|e| e.to_string())?;
+    let root_dom = parsercher::parse(&html).map_err(|e| e.to_string())?;
+
+    session.csrf_token = get_csrf_token(&root_dom)?;
+    session.accounts = get_accounts(&root_dom)?;
+    session.subaccounts = get_subaccounts(&root_dom)?;
+    session.categories = get_account_types(&root_dom)?;
+    session.balances = get_balances(&root_dom)?;
+
+    return Ok(());
+}
+
+/// a form scraped from a page of Moneyforward, filled to be posted.
+#[derive(Debug, PartialEq)]
+struct MfForm {
+    action: String,
+    fields: Vec<(String, String)>,
+}
+
+/// finds the "js-new-middle-category-form" of the large category and fills the name.
+fn get_middle_category_form(
+    root_dom: &Dom,
+    large_id: &str,
+    middle: &str,
+) -> Result<MfForm, String> {
+    let form_id = format!("middle-category-form-{}", large_id);
+    let mut needle_tag = Tag::new("div");
+    needle_tag.set_attr("id", &form_id);
+    let tags = parsercher::search_tag(&root_dom, &needle_tag)
+        .ok_or(format!("form [{}] not found", form_id))?;
+    let path = tags
+        .get(0)
+        .and_then(|it| it.get_attr("data-url"))
+        .ok_or(format!("form [{}] url not found", form_id))?;
+    let action = Url::parse("https://moneyforward.com/")
+        .and_then(|it| it.join(&path))
+        .map_err(|e| format!("form [{}] url err:{}", form_id, e))?;
+
+    let mut needle = parsercher::dom::Dom::new(parsercher::dom::DomType::Tag);
+    needle.set_tag(needle_tag);
+    let form = parsercher::search_dom(&root_dom, &needle)
+        .ok_or(format!("form [{}] not found", form_id))?;
+    let inputs = parsercher::search_tag(&form, &Tag::new("input"))
+        .ok_or(format!("form [{}] inputs not found", form_id))?;
+
+    let mut fields = Vec::new();
+    let mut has_name = false;
+    for input in inputs {
+        let name = match input.get_attr("name") {
+            Some(name) => name,
+            None => continue,
+        };
+        let class = input.get_attr("class").unwrap_or_default();
+        if class
+            .split_whitespace()
+            .any(|it| it == "js-middle-category-name")
+        {
+            has_name = true;
+            fields.push((name, middle.to_string()));
+        } else {
+            fields.push((name, input.get_attr("value").unwrap_or_default()));
+        }
+    }
+    if !has_name {
+        return Err(format!("form [{}] name field not found", form_id));
+    }
+
+    return Ok(MfForm {
+        action: action.to_string(),
+        fields,
+    });
+}
+
+fn create_middle_category(session: &MfSession, large: &str, middle: &str) -> Result<(), String> {
+    let category = session
+        .categories
+        .get(large)
+        .ok_or(format!("large category [{}] not found", large))?;
+
+    debug!("GET https://moneyforward.com/");
+    let res = session
+        .agent
+        .get("https://moneyforward.com/")
+        .call()
+        .map_err(|e| e.to_string())?;
+    let html = res.into_string().map_err(|e| e.to_string())?;
+    let root_dom = parsercher::parse(&html).map_err(|e| e.to_string())?;
+    let form = get_middle_category_form(&root_dom, &category.id, middle)?;
+
+    let fields: Vec<(&str, &str)> = form
+        .fields
+        .iter()
+        .map(|(k, v)| (k.as_str(), v.as_str()))
+        .collect();
+    debug!("POST {} {}", form.action, redact_form(&fields));
+    let _ = session
+        .agent
+        .post(&form.action)
+        .set("x-csrf-token", &session.csrf_token)
+        .send_form(&fields)
+        .map_err(|e| format!("http error:{}", e.to_string()))?;
+
+    return Ok(());
+}
+
+/// the page to add a manual account, linked from the list of accounts.
+const MANUAL_ACCOUNT_URL: &str = "https://moneyforward.com/accounts/new/wallet";
+
+fn is_tag_of(dom: &Dom, name: &str) -> bool {
+    return dom.get_tag().map_or(false, |it| it.get_name() == name);
+}
+
+fn find_tags<'a>(dom: &'a Dom, name: &str, found: &mut Vec<&'a Dom>) {
+    for child in dom.get_children().into_iter().flatten() {
+        if is_tag_of(child, name) {
+            found.push(child);
+        } else {
+            find_tags(child, name, found);
+        }
+    }
+}
+
+fn text_of(dom: &Dom) -> String {
+    let mut text = dom
+        .get_text()
+        .map(|it| it.get_text().to_string())
+        .unwrap_or_default();
+    for child in dom.get_children().into_iter().flatten() {
+        text.push_str(&text_of(child));
+    }
+    return text;
+}
+
+/// finds the form with a "[name]" text field on the page to add a manual account, and fills it.
+/// a select of the account type is set to the electronic money / prepaid one.
+fn get_manual_account_form(root_dom: &Dom, name: &str) -> Result<MfForm, String> {
+    let mut forms = Vec::new();
+    if is_tag_of(root_dom, "form") {
+        forms.push(root_dom);
+    }
+    find_tags(root_dom, "form", &mut forms);
+
+    for form in forms {
+        let tag = form.get_tag().ok_or("broken form html")?;
+        if tag
+            .get_attr("method")
+            .map_or(false, |it| it.eq_ignore_ascii_case("get"))
+        {
+            continue;
+        }
+        let action = match tag.get_attr("action") {
+            Some(action) => action,
+            None => continue,
+        };
+
+        let mut fields = Vec::new();
+        let mut has_name = false;
+        let mut inputs = Vec::new();
+        find_tags(form, "input", &mut inputs);
+        for input in inputs {
+            let input = input.get_tag().ok_or("broken form html")?;
+            let key = match input.get_attr("name") {
+                Some(key) => key,
+                None => continue,
+            };
+            let kind = input.get_attr("type").unwrap_or_default();
+            if (kind == "checkbox" || kind == "radio") && input.get_attr("checked").is_none() {
+                continue;
+            }
+            if !has_name && (kind.is_empty() || kind == "text") && key.ends_with("[name]") {
+                has_name = true;
+                fields.push((key, name.to_string()));
+            } else {
+                fields.push((key, input.get_attr("value").unwrap_or_default()));
+            }
+        }
+        if !has_name {
+            continue;
+        }
+
+        let mut selects = Vec::new();
+        find_tags(form, "select", &mut selects);
+        for select in selects {
+            let key = match select.get_tag().and_then(|it| it.get_attr("name")) {
+                Some(key) => key,
+                None => continue,
+            };
+            let mut options = Vec::new();
+            find_tags(select, "option", &mut options);
+            let option = options
+                .iter()
+                .find(|it| text_of(it).contains("プリペイド"))
+                .or_else(|| {
+                    options.iter().find(|it| {
+                        it.get_tag()
+                            .map_or(false, |t| t.get_attr("selected").is_some())
+                    })
+                })
+                .or_else(|| options.first());
+            if let Some(option) = option {
+                let value = option
+                    .get_tag()
+                    .and_then(|it| it.get_attr("value"))
+                    .unwrap_or_else(|| text_of(option).trim().to_string());
+                fields.push((key, value));
+            }
+        }
+
+        let action = Url::parse(MANUAL_ACCOUNT_URL)
+            .and_then(|it| it.join(&action))
+            .map_err(|e| format!("manual account form url err:{}", e))?;
+        return Ok(MfForm {
+            action: action.to_string(),
+            fields,
+        });
+    }
+
+    return Err("manual account form not found".to_string());
+}
+
+fn create_manual_account(session: &MfSession, name: &str) -> Result<(), String> {
+    debug!("GET {}", MANUAL_ACCOUNT_URL);
+    let res = session
+        .agent
+        .get(MANUAL_ACCOUNT_URL)
+        .call()
+        .map_err(|e| e.to_string())?;
+    let html = res.into_string().map_err(|e| e.to_string())?;
+    let root_dom = parsercher::parse(&html).map_err(|e| e.to_string())?;
+    let form = get_manual_account_form(&root_dom, name)?;
+
+    let fields: Vec<(&str, &str)> = form
+        .fields
+        .iter()
+        .map(|(k, v)| (k.as_str(), v.as_str()))
+        .collect();
+    debug!("POST {} {}", form.action, redact_form(&fields));
+    let _ = session
+        .agent
+        .post(&form.action)
+        .set("x-csrf-token", &session.csrf_token)
+        .send_form(&fields)
+        .map_err(|e| format!("http error:{}", e.to_string()))?;
+
+    return Ok(());
+}
+
+fn get_balance(session: &MfSession, account: &str) -> Result<i64, String> {
+    return session
+        .balances
+        .get(account)
+        .copied()
+        .ok_or(format!("balance of account [{}] not found", account));
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -664,9 +1391,7 @@ mod tests {
         );
     }
 
-    #[test]
-    fn get_account_types_test() {
-        let input = r#"
+    const CATEGORY_HTML: &str = r#"
 <ul class='dropdown-menu main_menu minus'>
 <li class='dropdown-submenu'>
 <a class='l_c_name' id='11'>食費</a>
@@ -716,6 +1441,10 @@ mod tests {
 
 
         "#;
+
+    #[test]
+    fn get_account_types_test() {
+        let input = CATEGORY_HTML;
         let root_dom = parsercher::parse(input).unwrap();
         let result = get_account_types(&root_dom).unwrap();
 
@@ -733,6 +1462,71 @@ mod tests {
         assert_eq!(d.subcategory["外食"], "42");
     }
 
+    #[test]
+    fn get_middle_category_form_test() {
+        let root_dom = parsercher::parse(CATEGORY_HTML).unwrap();
+        let form = get_middle_category_form(&root_dom, "11", "学食").unwrap();
+        assert_eq!(
+            form.action,
+            "https://moneyforward.com/middle_categories/create"
+        );
+        let fields: Vec<(&str, &str)> = form
+            .fields
+            .iter()
+            .map(|(k, v)| (k.as_str(), v.as_str()))
+            .collect();
+        assert_eq!(
+            fields,
+            vec![
+                ("middle_category_11[name]", "学食"),
+                ("middle_category_11[large_category_id]", "11"),
+                ("middle_category_11[transaction_page]", "true"),
+                ("middle_category_11[reload_type]", "1"),
+            ]
+        );
+        assert!(get_middle_category_form(&root_dom, "99", "学食").is_err());
+    }
+
+    #[test]
+    fn get_manual_account_form_test() {
+        let input = r#"
+<form action="/search" method="get"><input type="text" name="q[name]" value=""></form>
+<form class="new_user_asset_act" action="/accounts/create_manual" accept-charset="UTF-8" method="post">
+<input type="hidden" name="authenticity_token" value="token">
+<input type="text" name="user_asset_det_summary[name]" id="user_asset_det_summary_name">
+<select name="user_asset_det_summary[asset_subclass_id]">
+<option value="1">現金</option>
+<option value="2">電子マネー・プリペイド</option>
+</select>
+<input type="checkbox" name="user_asset_det_summary[is_hidden]" value="1">
+<input type="submit" name="commit" value="作成する">
+</form>
+        "#;
+        let root_dom = parsercher::parse(input).unwrap();
+        let form = get_manual_account_form(&root_dom, "大学生協").unwrap();
+        assert_eq!(
+            form.action,
+            "https://moneyforward.com/accounts/create_manual"
+        );
+        let fields: Vec<(&str, &str)> = form
+            .fields
+            .iter()
+            .map(|(k, v)| (k.as_str(), v.as_str()))
+            .collect();
+        assert_eq!(
+            fields,
+            vec![
+                ("authenticity_token", "token"),
+                ("user_asset_det_summary[name]", "大学生協"),
+                ("commit", "作成する"),
+                ("user_asset_det_summary[asset_subclass_id]", "2"),
+            ]
+        );
+
+        let root_dom = parsercher::parse(CATEGORY_HTML).unwrap();
+        assert!(get_manual_account_form(&root_dom, "大学生協").is_err());
+    }
+
     #[test]
     fn get_accounts_test() {
         let input = r#"
@@ -761,4 +1555,222 @@ mod tests {
             "1TD5ieGgTJi47Us30pemlTVclkgc7BG3Kq"
         );
     }
+
+    #[test]
+    fn get_balances_test() {
+        let input = r#"
+<ul class="facilities accounts-list"><li class="heading-category-name heading-normal">財布（現金管理）</li>
+<li class="account facilities-column border-bottom-dotted"><p class="heading-accounts">
+<a href="/accounts/show_manual/mEAiuPmpxuah1kCUuCTNGjHDC2DOoQW">財布</a></p><ul><li class="number">-1,234円</li>
+<li class="edit-links"><a href="/accounts/edit_manual/mEAiuPmpxuah1kCUuCTNGjHDC2DOoQW">編集</a></li></ul></li>
+<li class="heading-category-name heading-normal">電子マネー・プリペイド</li><li class="account facilities-column border-bottom-dotted">
+<p class="heading-accounts"><a href="/accounts/show_manual/GESAT1R0F0E8WMoP8K34DIcVqZo8M79JhfbG">大学生協</a></p><ul>
+<li class="number">11円</li><li class="edit-links"><a href="/accounts/edit_manual/GESAT1R0F0E8WMoP8K34DIcVqZo8M79JhfbG">編集</a></li></ul>
+</li></ul>
+        "#;
+        let root_dom = parsercher::parse(input).unwrap();
+
+        let result = get_balances(&root_dom).unwrap();
+
+        assert_eq!(result["大学生協"], 11);
+        assert_eq!(result["財布"], -1234);
+    }
+
+    #[test]
+    fn parse_yen_test() {
+        assert_eq!(parse_yen("11円"), Ok(11));
+        assert_eq!(parse_yen(" -12,345円 "), Ok(-12345));
+        assert!(parse_yen("円").is_err());
+    }
+
+    #[test]
+    fn parse_date_test() {
+        assert_eq!(parse_date("2022/07/26"), Ok(Tokyo.ymd(2022, 7, 26)));
+        assert_eq!(
+            parse_date("2022/02/30"),
+            Err("invalid date:2022/02/30".to_string())
+        );
+        assert!(parse_date("2022/07").is_err());
+    }
+
+    #[test]
+    fn mf_csv_test() {
+        let f = File::open("./src/testdata/mf_cf_20220720.csv").expect("file not found");
+        let data = parse_mf_csv(f, &NormalizeConfig::default()).unwrap();
+
+        assert_eq!(data.len(), 3);
+        assert!(data[0].target);
+        assert_eq!(data[0].date, Tokyo.ymd(2022, 7, 19));
+        assert_eq!(data[0].content, "唐揚げカレーM/ほうれん草");
+        assert_eq!(data[0].price, -473);
+        assert_eq!(data[0].bank, "大学生協");
+        assert_eq!(data[0].subcategory, "外食");
+        assert_eq!(data[0].id, "Xk3hXGx9sKcD2a1");
+        assert!(data[0].extra.is_empty());
+        assert_eq!(data[1].content, "チキンカツカレーM");
+        assert_eq!(data[1].memo, "昼食");
+        assert!(!data[2].target);
+        assert!(data[2].transfer);
+    }
+
+    /// returns at most `size` bytes per read, and an error at the end when `fail`.
+    struct ChunkReader {
+        data: Vec<u8>,
+        pos: usize,
+        size: usize,
+        fail: bool,
+    }
+
+    impl Read for ChunkReader {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            if self.pos >= self.data.len() && self.fail {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::ConnectionReset,
+                    "reset",
+                ));
+            }
+            let n = [buf.len(), self.size, self.data.len() - self.pos]
+                .iter()
+                .min()
+                .copied()
+                .unwrap();
+            buf[..n].copy_from_slice(&self.data[self.pos..self.pos + n]);
+            self.pos += n;
+            Ok(n)
+        }
+    }
+
+    fn decode(data: Vec<u8>, size: usize, fail: bool) -> std::io::Result<String> {
+        let rdr = ChunkReader {
+            data,
+            pos: 0,
+            size,
+            fail,
+        };
+        let mut text = String::new();
+        DecodeReader::new(rdr, encoding_rs::SHIFT_JIS).read_to_string(&mut text)?;
+        Ok(text)
+    }
+
+    #[test]
+    fn decode_reader_test() {
+        let text = std::fs::read_to_string("./src/testdata/mf_cf_20220720.csv").unwrap();
+        let (sjis, _, _) = encoding_rs::SHIFT_JIS.encode(&text);
+        let sjis = sjis.into_owned();
+
+        // multibyte characters split between reads
+        assert_eq!(decode(sjis.clone(), 3, false).unwrap(), text);
+
+        // no size limit
+        let large: Vec<u8> = sjis.iter().cycle().take(5 * 1024 * 1024).copied().collect();
+        let decoded = decode(large.clone(), 65536, false).unwrap();
+        assert!(decoded.len() > 5 * 1024 * 1024);
+
+        // cut in the middle of a character
+        let (kanji, _, _) = encoding_rs::SHIFT_JIS.encode("類");
+        let cut = sjis.windows(2).rposition(|it| it == &kanji[..]).unwrap() + 1;
+        let e = decode(sjis[..cut].to_vec(), 5, false).unwrap_err();
+        assert_eq!(e.kind(), std::io::ErrorKind::InvalidData);
+        assert!(e.to_string().starts_with("truncated Shift_JIS"));
+
+        let e = decode(sjis.clone(), 7, true).unwrap_err();
+        assert_eq!(e.kind(), std::io::ErrorKind::ConnectionReset);
+    }
+
+    #[test]
+    fn read_body_test() {
+        let body = "\"計算対象\",\"日付\"\n".as_bytes().to_vec();
+        let len = body.len().to_string();
+        let short = (body.len() + 10).to_string();
+
+        let read = |length: Option<&str>, encoding: Option<&str>| {
+            read_body(std::io::Cursor::new(body.clone()), length, encoding)
+        };
+        assert_eq!(read(Some(&len), None).unwrap(), body);
+        assert_eq!(read(None, None).unwrap(), body);
+        assert!(read(Some(&short), None).unwrap_err().contains("truncated"));
+        assert!(read(Some(&short), Some("identity")).is_err());
+        // the length of a compressed body
+        assert_eq!(read(Some(&short), Some("gzip")).unwrap(), body);
+        assert!(read(Some("x"), None).is_err());
+    }
+
+    #[test]
+    fn detect_encoding_test() {
+        let text = "\"計算対象\",\"日付\"";
+        let (sjis, _, _) = encoding_rs::SHIFT_JIS.encode(text);
+        assert_eq!(
+            detect_encoding(&sjis, None, "Shift_JIS").unwrap(),
+            encoding_rs::SHIFT_JIS
+        );
+        assert_eq!(
+            detect_encoding(text.as_bytes(), None, "Shift_JIS").unwrap(),
+            encoding_rs::UTF_8
+        );
+        // cut in the middle of a character
+        assert_eq!(
+            detect_encoding(&text.as_bytes()[..5], None, "Shift_JIS").unwrap(),
+            encoding_rs::UTF_8
+        );
+        assert_eq!(
+            detect_encoding(b"\xef\xbb\xbfID", None, "Shift_JIS").unwrap(),
+            encoding_rs::UTF_8
+        );
+        assert_eq!(
+            detect_encoding(b"ID,date", None, "Shift_JIS").unwrap(),
+            encoding_rs::SHIFT_JIS
+        );
+        assert!(detect_encoding(b"ID", None, "unknown").is_err());
+
+        // the declared charset is used when the bytes are valid in it.
+        assert_eq!(
+            detect_encoding(b"ID,date", Some("EUC-JP"), "Shift_JIS").unwrap(),
+            encoding_rs::EUC_JP
+        );
+        assert_eq!(
+            detect_encoding(&sjis, Some("Shift_JIS"), "UTF-8").unwrap(),
+            encoding_rs::SHIFT_JIS
+        );
+        // a false charset is ignored.
+        assert_eq!(
+            detect_encoding(&sjis, Some("utf-8"), "Shift_JIS").unwrap(),
+            encoding_rs::SHIFT_JIS
+        );
+        assert_eq!(
+            detect_encoding(text.as_bytes(), Some("unknown"), "Shift_JIS").unwrap(),
+            encoding_rs::UTF_8
+        );
+    }
+
+    #[test]
+    fn get_charset_test() {
+        assert_eq!(
+            get_charset("text/csv; charset=Shift_JIS"),
+            Some("Shift_JIS")
+        );
+        assert_eq!(get_charset("text/csv;Charset=\"utf-8\""), Some("utf-8"));
+        assert_eq!(get_charset("text/csv"), None);
+        assert_eq!(get_charset("text/csv; header=present"), None);
+    }
+
+    #[test]
+    fn mf_csv_reordered_test() {
+        let data = "ID,金額,日付,内容,タグ\nid1,-407,2022/07/15,味噌汁,生協\n";
+        let data = parse_mf_csv(data.as_bytes(), &NormalizeConfig::default()).unwrap();
+        assert_eq!(data[0].id, "id1");
+        assert_eq!(data[0].price, -407);
+        assert_eq!(data[0].content, "味噌汁");
+        assert_eq!(data[0].bank, "");
+        assert_eq!(data[0].extra["タグ"], "生協");
+
+        let data = "ID,金額,日付,内容\nid1,--,2022/07/15,味噌汁\n";
+        let result = parse_mf_csv(data.as_bytes(), &NormalizeConfig::default());
+        assert!(result
+            .unwrap_err()
+            .starts_with("csv line 2: invalid price data type"));
+        assert_eq!(
+            parse_mf_csv("ID,日付,内容\n".as_bytes(), &NormalizeConfig::default()).unwrap_err(),
+            "column [金額] not found"
+        );
+    }
 }
@@ -0,0 +1,122 @@
+use std::collections::BTreeMap;
+use unicode_normalization::UnicodeNormalization;
+
+/// rules applied to texts of both seikyo and Moneyforward before comparison.
+#[derive(serde_derive::Deserialize, Debug)]
+pub struct NormalizeConfig {
+    /// NFKC(全角英数字を半角、半角カナを全角にして濁点を結合するなど)
+    #[serde(default = "default_true")]
+    pub nfkc: bool,
+    /// 半角カナを全角にして濁点を結合する(nfkcを使わないとき)
+    #[serde(default = "default_true")]
+    pub kana: bool,
+    /// ダッシュと波線の異体字をそろえる
+    #[serde(default = "default_true")]
+    pub dashes: bool,
+    /// 連続する空白をまとめて前後の空白を削る
+    #[serde(default = "default_true")]
+    pub spaces: bool,
+    /// 追加の置き換え
+    #[serde(default)]
+    pub replace: BTreeMap<String, String>,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+impl Default for NormalizeConfig {
+    fn default() -> Self {
+        NormalizeConfig {
+            nfkc: true,
+            kana: true,
+            dashes: true,
+            spaces: true,
+            replace: BTreeMap::new(),
+        }
+    }
+}
+
+const DASHES: [char; 8] = [
+    '\u{2010}', '\u{2011}', '\u{2012}', '\u{2013}', '\u{2014}', '\u{2015}', '\u{2212}', '\u{FF0D}',
+];
+const WAVES: [char; 2] = ['\u{301C}', '\u{FF5E}'];
+
+pub fn normalize(conf: &NormalizeConfig, text: &str) -> String {
+    let mut text = if conf.nfkc {
+        text.nfkc().collect::<String>()
+    } else if conf.kana {
+        kana::combine(&kana::half2full(text))
+    } else {
+        text.to_string()
+    };
+
+    if conf.dashes {
+        text = text
+            .chars()
+            .map(|c| match c {
+                c if DASHES.contains(&c) => '-',
+                c if WAVES.contains(&c) => '~',
+                c => c,
+            })
+            .collect();
+    }
+
+    for (from, to) in &conf.replace {
+        text = text.replace(from.as_str(), to);
+    }
+
+    if conf.spaces {
+        text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    }
+
+    return text;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalize_test() {
+        let conf = NormalizeConfig::default();
+        assert_eq!(
+            normalize(&conf, "唐揚げｶﾚｰM/ほうれん草"),
+            "唐揚げカレーM/ほうれん草"
+        );
+        assert_eq!(normalize(&conf, "京大ルネＤ"), "京大ルネD");
+        assert_eq!(normalize(&conf, "ｶﾞﾗﾅ ｿｰﾀﾞ"), "ガラナ ソーダ");
+        // combining dakuten
+        assert_eq!(normalize(&conf, "か\u{3099}め煮"), "がめ煮");
+        assert_eq!(
+            normalize(&conf, "　ほうれん草　 /  味噌汁 "),
+            "ほうれん草 / 味噌汁"
+        );
+        assert_eq!(normalize(&conf, "カレー〜中辛－"), "カレー~中辛-");
+
+        let conf = NormalizeConfig {
+            nfkc: false,
+            dashes: false,
+            replace: [("Ｍ".to_string(), "(中)".to_string())]
+                .into_iter()
+                .collect(),
+            ..NormalizeConfig::default()
+        };
+        assert_eq!(normalize(&conf, "唐揚げｶﾚｰＭ〜"), "唐揚げカレー(中)〜");
+    }
+
+    #[test]
+    fn normalize_csv_test() {
+        // the same purchase downloaded from seikyo and from Moneyforward.
+        let conf = NormalizeConfig::default();
+        let sk = std::fs::read_to_string("./src/testdata/prepaidHistory_20220720.csv").unwrap();
+        let sk_line = sk.lines().nth(2).unwrap();
+        assert_eq!(
+            normalize(&conf, sk_line),
+            "\"7/19(火)\",\"京大ルネD\",\"唐揚げカレーM/ほうれん草\",\"2.3\",\"473\""
+        );
+        let mf = std::fs::read_to_string("./src/testdata/mf_cf_20220720.csv").unwrap();
+        let mf_line = mf.lines().nth(1).unwrap();
+        assert!(normalize(&conf, mf_line).contains(",\"唐揚げカレーM/ほうれん草\","));
+    }
+}
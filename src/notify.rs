@@ -0,0 +1,452 @@
+use crate::summary::RunSummary;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::process::{Command, Stdio};
+use std::time::Duration;
+
+/// a notification must not hang the run on a dead server.
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(serde_derive::Deserialize, Debug)]
+pub struct NotifyConfig {
+    #[serde(default)]
+    pub when: NotifyWhen,
+    pub webhook: Option<WebhookConfig>,
+    pub command: Option<CommandConfig>,
+    pub smtp: Option<SmtpConfig>,
+}
+
+#[derive(serde_derive::Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum NotifyWhen {
+    /// 毎回通知する
+    Always,
+    /// 失敗したときだけ通知する
+    Failure,
+}
+
+impl Default for NotifyWhen {
+    fn default() -> Self {
+        NotifyWhen::Failure
+    }
+}
+
+#[derive(serde_derive::Deserialize, Debug)]
+pub struct WebhookConfig {
+    pub url: String,
+}
+
+#[derive(serde_derive::Deserialize, Debug)]
+pub struct CommandConfig {
+    pub command: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+}
+
+#[derive(serde_derive::Deserialize, Debug)]
+pub struct SmtpConfig {
+    pub server: String,
+    #[serde(default = "default_smtp_port")]
+    pub port: u16,
+    /// SMTPS(implicit TLS). otherwise STARTTLS is required to authenticate.
+    #[serde(default)]
+    pub tls: bool,
+    pub user: Option<String>,
+    pub pass: Option<String>,
+    pub from: String,
+    pub to: String,
+}
+
+fn default_smtp_port() -> u16 {
+    25
+}
+
+/// sends the summary to every configured target. failures of each target are collected.
+pub fn send_notification(conf: &NotifyConfig, summary: &RunSummary) -> Result<(), String> {
+    if conf.when == NotifyWhen::Failure && summary.is_success() {
+        return Ok(());
+    }
+
+    let text = summary.to_text();
+    let mut errors = Vec::new();
+
+    if let Some(webhook) = &conf.webhook {
+        if let Err(e) = send_webhook(webhook, summary, &text) {
+            errors.push(format!("webhook:{}", e));
+        }
+    }
+    if let Some(command) = &conf.command {
+        if let Err(e) = run_command(command, summary, &text) {
+            errors.push(format!("command:{}", e));
+        }
+    }
+    if let Some(smtp) = &conf.smtp {
+        if let Err(e) = send_mail(smtp, summary, &text) {
+            errors.push(format!("smtp:{}", e));
+        }
+    }
+
+    if errors.is_empty() {
+        return Ok(());
+    }
+    return Err(errors.join(", "));
+}
+
+fn status(summary: &RunSummary) -> &'static str {
+    if summary.is_success() {
+        "success"
+    } else {
+        "failure"
+    }
+}
+
+#[derive(serde_derive::Serialize)]
+struct WebhookPayload<'a> {
+    /// plain text for chat services such as Slack
+    text: &'a str,
+    status: &'a str,
+    summary: &'a RunSummary,
+}
+
+fn send_webhook(conf: &WebhookConfig, summary: &RunSummary, text: &str) -> Result<(), String> {
+    let payload = serde_json::to_string(&WebhookPayload {
+        text,
+        status: status(summary),
+        summary,
+    })
+    .map_err(|e| e.to_string())?;
+
+    let agent = ureq::builder()
+        .timeout_connect(TIMEOUT)
+        .timeout(TIMEOUT)
+        .build();
+    agent
+        .post(&conf.url)
+        .set("Content-Type", "application/json")
+        .send_string(&payload)
+        .map_err(|e| format!("http err:{}", e))?;
+
+    return Ok(());
+}
+
+fn run_command(conf: &CommandConfig, summary: &RunSummary, text: &str) -> Result<(), String> {
+    let mut child = Command::new(&conf.command)
+        .args(&conf.args)
+        .env("SKMF_STATUS", status(summary))
+        .stdin(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("[{}] spawn err:{}", conf.command, e))?;
+
+    child
+        .stdin
+        .take()
+        .ok_or("stdin not available")?
+        .write_all(text.as_bytes())
+        .map_err(|e| e.to_string())?;
+
+    let result = child.wait().map_err(|e| e.to_string())?;
+    if !result.success() {
+        return Err(format!("[{}] exit with {}", conf.command, result));
+    }
+    return Ok(());
+}
+
+/// connects to the first address of the server which answers in time.
+fn connect(server: &str, port: u16) -> Result<TcpStream, String> {
+    let addrs = (server, port)
+        .to_socket_addrs()
+        .map_err(|e| format!("connect err:{}", e))?;
+    let mut error = format!("connect err:[{}] not found", server);
+    for addr in addrs {
+        match TcpStream::connect_timeout(&addr, TIMEOUT) {
+            Ok(stream) => {
+                stream
+                    .set_read_timeout(Some(TIMEOUT))
+                    .and_then(|_| stream.set_write_timeout(Some(TIMEOUT)))
+                    .map_err(|e| format!("connect err:{}", e))?;
+                return Ok(stream);
+            }
+            Err(e) => error = format!("connect err:{}", e),
+        }
+    }
+    return Err(error);
+}
+
+fn send_mail(conf: &SmtpConfig, summary: &RunSummary, text: &str) -> Result<(), String> {
+    let stream = connect(&conf.server, conf.port)?;
+
+    let message = build_mail(conf, summary, text);
+    let connector = native_tls::TlsConnector::new().map_err(|e| e.to_string())?;
+
+    if conf.tls {
+        let stream = connector
+            .connect(&conf.server, stream)
+            .map_err(|e| format!("tls err:{}", e))?;
+        let mut stream = BufReader::new(stream);
+        smtp_reply(&mut stream, 220)?;
+        smtp_command(&mut stream, "EHLO localhost", 250)?;
+        return smtp_session(stream, conf, &message, true);
+    }
+
+    let mut stream = BufReader::new(stream);
+    smtp_reply(&mut stream, 220)?;
+    smtp_command(&mut stream, "EHLO localhost", 250)?;
+    if conf.user.is_none() && conf.pass.is_none() {
+        return smtp_session(stream, conf, &message, false);
+    }
+
+    // credentials are never sent in cleartext.
+    smtp_command(&mut stream, "STARTTLS", 220)
+        .map_err(|e| format!("STARTTLS is required to authenticate:{}", e))?;
+    let stream = connector
+        .connect(&conf.server, stream.into_inner())
+        .map_err(|e| format!("tls err:{}", e))?;
+    let mut stream = BufReader::new(stream);
+    smtp_command(&mut stream, "EHLO localhost", 250)?;
+    return smtp_session(stream, conf, &message, true);
+}
+
+fn build_mail(conf: &SmtpConfig, summary: &RunSummary, text: &str) -> String {
+    let mut message = String::new();
+    message.push_str(&format!("From: {}\r\n", conf.from));
+    message.push_str(&format!("To: {}\r\n", conf.to));
+    message.push_str(&format!("Subject: skmf {}\r\n", status(summary)));
+    message.push_str("MIME-Version: 1.0\r\n");
+    message.push_str("Content-Type: text/plain; charset=UTF-8\r\n");
+    message.push_str("Content-Transfer-Encoding: base64\r\n");
+    message.push_str("\r\n");
+
+    let body = base64(text.as_bytes());
+    for line in body.as_bytes().chunks(76) {
+        message.push_str(std::str::from_utf8(line).unwrap_or_default());
+        message.push_str("\r\n");
+    }
+    return message;
+}
+
+/// sends the message after the greeting and EHLO. `secure` is whether the stream is TLS.
+fn smtp_session<S: Read + Write>(
+    mut stream: BufReader<S>,
+    conf: &SmtpConfig,
+    message: &str,
+    secure: bool,
+) -> Result<(), String> {
+    if let (Some(user), Some(pass)) = (&conf.user, &conf.pass) {
+        if !secure {
+            return Err("refused to authenticate without TLS".to_string());
+        }
+        let token = base64(format!("\0{}\0{}", user, pass).as_bytes());
+        smtp_command(&mut stream, &format!("AUTH PLAIN {}", token), 235)
+            .map_err(|_| "authentication failure".to_string())?;
+    }
+
+    smtp_command(&mut stream, &format!("MAIL FROM:<{}>", conf.from), 250)?;
+    smtp_command(&mut stream, &format!("RCPT TO:<{}>", conf.to), 250)?;
+    smtp_command(&mut stream, "DATA", 354)?;
+    smtp_command(&mut stream, &format!("{}.", message), 250)?;
+    smtp_command(&mut stream, "QUIT", 221)?;
+
+    return Ok(());
+}
+
+fn smtp_command<S: Read + Write>(
+    stream: &mut BufReader<S>,
+    command: &str,
+    code: u32,
+) -> Result<(), String> {
+    stream
+        .get_mut()
+        .write_all(format!("{}\r\n", command).as_bytes())
+        .map_err(|e| format!("smtp write err:{}", e))?;
+    return smtp_reply(stream, code);
+}
+
+fn smtp_reply<S: Read + Write>(stream: &mut BufReader<S>, code: u32) -> Result<(), String> {
+    loop {
+        let mut line = String::new();
+        let len = stream
+            .read_line(&mut line)
+            .map_err(|e| format!("smtp read err:{}", e))?;
+        if len == 0 {
+            return Err("smtp connection closed".to_string());
+        }
+        // "250-SIZE" continues, "250 OK" is the last line.
+        if line.len() > 3 && line.as_bytes()[3] == b'-' {
+            continue;
+        }
+        let reply = line
+            .get(0..3)
+            .and_then(|c| c.parse::<u32>().ok())
+            .ok_or(format!("smtp broken reply:{}", line.trim_end()))?;
+        if reply != code {
+            return Err(format!("smtp unexpected reply:{}", line.trim_end()));
+        }
+        return Ok(());
+    }
+}
+
+fn base64(data: &[u8]) -> String {
+    const TABLE: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::new();
+
+    for chunk in data.chunks(3) {
+        let b = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let n = (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(TABLE[(n >> (18 - i * 6) & 0x3f) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    return encoded;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn base64_test() {
+        assert_eq!(base64(b""), "");
+        assert_eq!(base64(b"f"), "Zg==");
+        assert_eq!(base64(b"fo"), "Zm8=");
+        assert_eq!(base64(b"foo"), "Zm9v");
+        assert_eq!(base64(b"\0user\0pass"), "AHVzZXIAcGFzcw==");
+        assert_eq!(base64("生協".as_bytes()), "55Sf5Y2U");
+    }
+
+    /// replays the server replies and records the commands.
+    struct MockStream {
+        input: std::io::Cursor<Vec<u8>>,
+        output: Vec<u8>,
+    }
+
+    impl Read for MockStream {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            self.input.read(buf)
+        }
+    }
+
+    impl Write for MockStream {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.output.write(buf)
+        }
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn get_smtp_config(auth: bool) -> SmtpConfig {
+        let auth = if auth {
+            "user=\"me\"\npass=\"secret\"\n"
+        } else {
+            ""
+        };
+        return toml::from_str(&format!(
+            "server=\"mail.example.com\"\nfrom=\"skmf@example.com\"\nto=\"me@example.com\"\n{}",
+            auth
+        ))
+        .unwrap();
+    }
+
+    #[test]
+    fn smtp_session_test() {
+        let replies = "250 OK\r\n250 OK\r\n354 go\r\n250 OK\r\n221 bye\r\n";
+        let mut stream = MockStream {
+            input: std::io::Cursor::new(replies.as_bytes().to_vec()),
+            output: Vec::new(),
+        };
+        let conf = get_smtp_config(false);
+        let result = smtp_session(BufReader::new(&mut stream), &conf, "body\r\n", false);
+        assert_eq!(result, Ok(()));
+        let sent = String::from_utf8(stream.output).unwrap();
+        assert!(sent.starts_with("MAIL FROM:<skmf@example.com>\r\n"));
+
+        let mut stream = MockStream {
+            input: std::io::Cursor::new(b"235 OK\r\n".to_vec()),
+            output: Vec::new(),
+        };
+        let conf = get_smtp_config(true);
+        assert!(smtp_session(BufReader::new(&mut stream), &conf, "body\r\n", false).is_err());
+        assert!(stream.output.is_empty());
+    }
+
+    #[test]
+    fn smtp_auth_failure_test() {
+        let mut stream = MockStream {
+            input: std::io::Cursor::new(b"535 5.7.8 failed\r\n".to_vec()),
+            output: Vec::new(),
+        };
+        let conf = get_smtp_config(true);
+        let result = smtp_session(BufReader::new(&mut stream), &conf, "body\r\n", true);
+        assert_eq!(result, Err("authentication failure".to_string()));
+        let sent = String::from_utf8(stream.output).unwrap();
+        assert_eq!(sent, "AUTH PLAIN AG1lAHNlY3JldA==\r\n");
+    }
+
+    #[test]
+    fn starttls_refused_test() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut stream = BufReader::new(stream);
+            let mut received = Vec::new();
+            stream.get_mut().write_all(b"220 hello\r\n").unwrap();
+            for reply in ["250 OK\r\n", "502 not implemented\r\n"] {
+                let mut line = String::new();
+                stream.read_line(&mut line).unwrap();
+                received.push(line);
+                stream.get_mut().write_all(reply.as_bytes()).unwrap();
+            }
+            // nothing is sent after the refusal
+            let mut rest = String::new();
+            stream.read_to_string(&mut rest).unwrap();
+            received.push(rest);
+            received
+        });
+
+        let conf = SmtpConfig {
+            server: "127.0.0.1".to_string(),
+            port,
+            ..get_smtp_config(true)
+        };
+        let result = send_mail(&conf, &RunSummary::default(), "body");
+        assert!(result
+            .unwrap_err()
+            .starts_with("STARTTLS is required to authenticate"));
+        assert_eq!(
+            server.join().unwrap(),
+            vec!["EHLO localhost\r\n", "STARTTLS\r\n", ""]
+        );
+    }
+
+    #[test]
+    fn notify_when_test() {
+        let conf: NotifyConfig = toml::from_str("").unwrap();
+        assert_eq!(conf.when, NotifyWhen::Failure);
+        let conf: NotifyConfig = toml::from_str("when=\"always\"").unwrap();
+        assert_eq!(conf.when, NotifyWhen::Always);
+        assert!(toml::from_str::<NotifyConfig>("when=\"allways\"").is_err());
+    }
+
+    #[test]
+    fn smtp_reply_test() {
+        let input = "250-mail.example.com\r\n250-AUTH PLAIN\r\n250 OK\r\n";
+        let mut stream = BufReader::new(std::io::Cursor::new(input.as_bytes().to_vec()));
+        assert_eq!(smtp_reply(&mut stream, 250), Ok(()));
+        assert!(smtp_reply(&mut stream, 250).is_err());
+
+        let mut stream = BufReader::new(std::io::Cursor::new(b"535 failed\r\n".to_vec()));
+        assert_eq!(
+            smtp_reply(&mut stream, 235),
+            Err("smtp unexpected reply:535 failed".to_string())
+        );
+    }
+}
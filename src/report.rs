@@ -0,0 +1,250 @@
+use crate::mf::MfDatum;
+use crate::sk::{SkDataType, SkDatum};
+use chrono::Date;
+use chrono_tz::Tz;
+use std::fs::File;
+use std::io::BufWriter;
+
+#[derive(serde_derive::Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum RecordStatus {
+    /// 登録済みの明細が見つかった
+    Matched,
+    /// 未登録
+    Missing,
+    /// 送信済み(ジャーナルにあるがMoneyforwardにまだ出てこない)
+    Journaled,
+    /// 今回送信する
+    Pending,
+    Sent,
+    Failed,
+}
+
+#[derive(serde_derive::Serialize, Debug)]
+pub struct RecordReport {
+    pub kind: String,
+    pub date: String,
+    pub shop: String,
+    pub menu: String,
+    pub price: u32,
+    /// position in the seikyo record the item was split from. None for whole records
+    pub item: Option<usize>,
+    pub status: RecordStatus,
+    /// MfDatum.id of the matched entry
+    pub mf_id: Option<String>,
+    /// why the record was considered already synced
+    pub match_reason: Option<String>,
+    /// MfDatum.id of the entry with the same date and price but different content
+    pub similar_mf_id: Option<String>,
+    pub error: Option<String>,
+}
+
+/// Moneyforward entry without seikyo counterpart
+#[derive(serde_derive::Serialize, Debug)]
+pub struct OrphanReport {
+    pub kind: String,
+    pub date: String,
+    pub content: String,
+    pub price: i32,
+    pub mf_id: String,
+}
+
+#[derive(serde_derive::Serialize, Debug)]
+pub struct MonthReport {
+    pub month: String,
+    pub records: Vec<RecordReport>,
+    pub orphans: Vec<OrphanReport>,
+}
+
+/// detail of one run written by `--report`.
+#[derive(serde_derive::Serialize, Debug, Default)]
+pub struct RunReport {
+    pub months: Vec<MonthReport>,
+    pub errors: Vec<String>,
+    pub login_seconds: f64,
+    pub fetch_seconds: f64,
+}
+
+fn kind_name(dtype: SkDataType) -> &'static str {
+    match dtype {
+        SkDataType::PaymentHistory => "payment",
+        SkDataType::PrepaidHistory => "prepaid",
+    }
+}
+
+impl RunReport {
+    pub fn month(&mut self, date: Date<Tz>) -> &mut MonthReport {
+        self.months.push(MonthReport {
+            month: date.format("%Y/%m").to_string(),
+            records: Vec::new(),
+            orphans: Vec::new(),
+        });
+        return self.months.last_mut().unwrap();
+    }
+}
+
+impl MonthReport {
+    pub fn add(
+        &mut self,
+        dtype: SkDataType,
+        datum: &SkDatum,
+        mf_id: Option<&String>,
+        match_reason: Option<&String>,
+        similar_mf_id: Option<&String>,
+    ) {
+        self.records.push(RecordReport {
+            kind: kind_name(dtype).to_string(),
+            date: datum.date.format("%Y/%m/%d").to_string(),
+            shop: datum.shop.clone(),
+            menu: datum.menu.clone(),
+            price: datum.price,
+            item: datum.item_of.as_ref().map(|it| it.index),
+            status: match mf_id {
+                Some(_) => RecordStatus::Matched,
+                None => RecordStatus::Missing,
+            },
+            mf_id: mf_id.cloned(),
+            match_reason: match_reason.cloned(),
+            similar_mf_id: similar_mf_id.cloned(),
+            error: None,
+        });
+    }
+
+    pub fn add_orphan(&mut self, dtype: SkDataType, c: &MfDatum) {
+        self.orphans.push(OrphanReport {
+            kind: kind_name(dtype).to_string(),
+            date: c.date.format("%Y/%m/%d").to_string(),
+            content: c.content.clone(),
+            price: c.price,
+            mf_id: c.id.clone(),
+        });
+    }
+
+    /// changes the status of the first record equal to `datum` in the status `from`.
+    pub fn mark(
+        &mut self,
+        dtype: SkDataType,
+        datum: &SkDatum,
+        from: RecordStatus,
+        to: RecordStatus,
+    ) -> Option<&mut RecordReport> {
+        let kind = kind_name(dtype);
+        let date = datum.date.format("%Y/%m/%d").to_string();
+
+        let record = self.records.iter_mut().find(|it| {
+            it.status == from
+                && it.kind == kind
+                && it.date == date
+                && it.price == datum.price
+                && it.menu == datum.menu
+        })?;
+        record.status = to;
+        return Some(record);
+    }
+
+    /// marks records left missing after the journal reconciliation.
+    pub fn mark_journaled(&mut self, dtype: SkDataType) {
+        let kind = kind_name(dtype);
+        for it in self.records.iter_mut() {
+            if it.kind == kind && it.status == RecordStatus::Missing {
+                it.status = RecordStatus::Journaled;
+            }
+        }
+    }
+}
+
+pub fn save_report(path: &str, report: &RunReport) -> Result<(), String> {
+    let file = BufWriter::new(
+        File::create(path).map_err(|e| format!("report[{}] create err:{}", path, e))?,
+    );
+    serde_json::to_writer_pretty(file, report).map_err(|e| e.to_string())?;
+
+    return Ok(());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sk::make_datum;
+    use chrono::TimeZone;
+    use chrono_tz::Asia::Tokyo;
+
+    #[test]
+    fn mark_test() {
+        let mut report = RunReport::default();
+        let month = report.month(Tokyo.ymd(2022, 7, 1));
+
+        let id = "id1".to_string();
+        month.add(
+            SkDataType::PrepaidHistory,
+            &make_datum(Tokyo.ymd(2022, 7, 5), "京大ルネＤ", "menu1", 407),
+            Some(&id),
+            None,
+            None,
+        );
+        month.add(
+            SkDataType::PrepaidHistory,
+            &make_datum(Tokyo.ymd(2022, 7, 5), "京大ルネＤ", "menu1", 407),
+            None,
+            None,
+            None,
+        );
+        month.add(
+            SkDataType::PrepaidHistory,
+            &make_datum(Tokyo.ymd(2022, 7, 5), "京大ルネＤ", "menu1", 407),
+            None,
+            None,
+            None,
+        );
+        month.add(
+            SkDataType::PrepaidHistory,
+            &make_datum(Tokyo.ymd(2022, 7, 5), "京大ルネＤ", "menu2", 473),
+            None,
+            None,
+            None,
+        );
+
+        let datum = make_datum(Tokyo.ymd(2022, 7, 5), "京大ルネＤ", "menu1", 407);
+        month
+            .mark(
+                SkDataType::PrepaidHistory,
+                &datum,
+                RecordStatus::Missing,
+                RecordStatus::Pending,
+            )
+            .unwrap();
+        month.mark_journaled(SkDataType::PaymentHistory);
+        assert_eq!(month.records[2].status, RecordStatus::Missing);
+        month.mark_journaled(SkDataType::PrepaidHistory);
+        let record = month
+            .mark(
+                SkDataType::PrepaidHistory,
+                &datum,
+                RecordStatus::Pending,
+                RecordStatus::Failed,
+            )
+            .unwrap();
+        record.error = Some("http error".to_string());
+
+        assert!(month
+            .mark(
+                SkDataType::PaymentHistory,
+                &datum,
+                RecordStatus::Journaled,
+                RecordStatus::Sent,
+            )
+            .is_none());
+
+        let status: Vec<_> = month.records.iter().map(|it| it.status).collect();
+        assert_eq!(
+            status,
+            vec![
+                RecordStatus::Matched,
+                RecordStatus::Failed,
+                RecordStatus::Journaled,
+                RecordStatus::Journaled,
+            ]
+        );
+        assert_eq!(report.months[0].records[0].mf_id, Some("id1".to_string()));
+    }
+}
@@ -0,0 +1,174 @@
+use crate::normalize::{normalize, NormalizeConfig};
+use std::collections::HashMap;
+
+/// an entry of the shop table keyed by the shop name of seikyo like "京大ルネＤ".
+/// keys are compared after normalization.
+#[derive(serde_derive::Deserialize, Debug)]
+pub struct ShopConfig {
+    /// 表示名
+    pub name: Option<String>,
+    /// キャンパス
+    pub campus: Option<String>,
+    /// この店舗の明細を登録するカテゴリの大分類
+    pub large_category: Option<String>,
+    /// この店舗の明細を登録するカテゴリの中分類
+    pub middle_category: Option<String>,
+    /// この店舗でのチャージを登録するカテゴリの大分類
+    pub charge_large_category: Option<String>,
+    /// この店舗でのチャージを登録するカテゴリの中分類
+    pub charge_middle_category: Option<String>,
+}
+
+pub type ShopTable = HashMap<String, ShopConfig>;
+
+/// a shop resolved by the shop table.
+#[derive(Debug, PartialEq)]
+pub struct Shop<'a> {
+    pub name: String,
+    pub campus: Option<&'a str>,
+    pub large_category: &'a str,
+    pub middle_category: &'a str,
+}
+
+impl<'a> Shop<'a> {
+    /// "name (campus)"
+    pub fn label(&self) -> String {
+        match self.campus {
+            Some(campus) => format!("{} ({})", self.name, campus),
+            None => self.name.clone(),
+        }
+    }
+}
+
+/// keys the table by the normalized shop names, so that a key is found however it is written.
+pub fn normalize_keys(shops: ShopTable, norm: &NormalizeConfig) -> Result<ShopTable, String> {
+    let mut table = ShopTable::new();
+    for (key, conf) in shops {
+        let name = normalize(norm, key.trim());
+        if table.insert(name.clone(), conf).is_some() {
+            return Err(format!("shops [{}] is written twice", name));
+        }
+    }
+    return Ok(table);
+}
+
+/// resolves the raw shop name with the categories of purchases. shops not in the table are named
+/// by the normalized raw name and get the default categories.
+pub fn get_shop<'a>(
+    shops: &'a ShopTable,
+    raw: &str,
+    norm: &NormalizeConfig,
+    large_category: &'a str,
+    middle_category: &'a str,
+) -> Shop<'a> {
+    let conf = shops.get(&normalize(norm, raw.trim()));
+    return Shop {
+        large_category: conf
+            .and_then(|it| it.large_category.as_deref())
+            .unwrap_or(large_category),
+        middle_category: conf
+            .and_then(|it| it.middle_category.as_deref())
+            .unwrap_or(middle_category),
+        ..get_shop_name(conf, raw, norm)
+    };
+}
+
+/// resolves the raw shop name with the categories of charges.
+pub fn get_charge_shop<'a>(
+    shops: &'a ShopTable,
+    raw: &str,
+    norm: &NormalizeConfig,
+    large_category: &'a str,
+    middle_category: &'a str,
+) -> Shop<'a> {
+    let conf = shops.get(&normalize(norm, raw.trim()));
+    return Shop {
+        large_category: conf
+            .and_then(|it| it.charge_large_category.as_deref())
+            .unwrap_or(large_category),
+        middle_category: conf
+            .and_then(|it| it.charge_middle_category.as_deref())
+            .unwrap_or(middle_category),
+        ..get_shop_name(conf, raw, norm)
+    };
+}
+
+fn get_shop_name<'a>(conf: Option<&'a ShopConfig>, raw: &str, norm: &NormalizeConfig) -> Shop<'a> {
+    let name = match conf.and_then(|it| it.name.as_ref()) {
+        Some(name) => name.clone(),
+        None => normalize(norm, raw),
+    };
+    return Shop {
+        name,
+        campus: conf.and_then(|it| it.campus.as_deref()),
+        large_category: "",
+        middle_category: "",
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn get_shop_test() {
+        let shops: ShopTable = toml::from_str(
+            r#"
+            ["京大ルネＤ"]
+            name = "ルネ食堂"
+            campus = "吉田"
+
+            ["京大カフェＢ"]
+            large_category = "食費"
+            middle_category = "カフェ"
+            charge_middle_category = "プリペイド"
+
+            ["京大北部D"]
+            name = "北部食堂"
+            "#,
+        )
+        .unwrap();
+        let norm = NormalizeConfig::default();
+        let shops = normalize_keys(shops, &norm).unwrap();
+
+        let shop = get_shop(&shops, "京大ルネＤ", &norm, "食費", "外食");
+        assert_eq!(shop.label(), "ルネ食堂 (吉田)");
+        assert_eq!(shop.middle_category, "外食");
+
+        let shop = get_shop(&shops, "京大カフェＢ", &norm, "食費", "外食");
+        assert_eq!(shop.label(), "京大カフェB");
+        assert_eq!(shop.large_category, "食費");
+        assert_eq!(shop.middle_category, "カフェ");
+
+        // written normalized in the table
+        let shop = get_shop(&shops, "京大北部Ｄ", &norm, "食費", "外食");
+        assert_eq!(shop.label(), "北部食堂");
+
+        let shop = get_shop(&shops, "京大南部Ｄ", &norm, "食費", "外食");
+        assert_eq!(
+            shop,
+            Shop {
+                name: "京大南部D".to_string(),
+                campus: None,
+                large_category: "食費",
+                middle_category: "外食",
+            }
+        );
+
+        let shop = get_charge_shop(&shops, "京大カフェＢ", &norm, "現金・カード", "電子マネー");
+        assert_eq!(shop.large_category, "現金・カード");
+        assert_eq!(shop.middle_category, "プリペイド");
+        let norm = NormalizeConfig {
+            nfkc: false,
+            kana: false,
+            ..NormalizeConfig::default()
+        };
+        let shop = get_charge_shop(&shops, "京大南部Ｄ", &norm, "現金・カード", "電子マネー");
+        assert_eq!(shop.label(), "京大南部Ｄ");
+        assert_eq!(shop.middle_category, "電子マネー");
+
+        let twice: ShopTable =
+            toml::from_str("[\"京大ルネＤ\"]\nname=\"a\"\n[\"京大ルネD\"]\nname=\"b\"\n").unwrap();
+        assert!(normalize_keys(twice, &NormalizeConfig::default()).is_err());
+    }
+}
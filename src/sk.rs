@@ -1,20 +1,68 @@
+//! client of the seikyo prepaid mypage (https://mp.seikyou.jp/mypage/).
+//!
+//! ```no_run
+//! use skmf::normalize::NormalizeConfig;
+//! use skmf::sk::{SkClient, SkDataType, SkUser};
+//!
+//! let user = SkUser { user: "id".to_string(), pass: "pass".to_string() };
+//! let client = SkClient::login(user)?;
+//! let date = skmf::sync::get_date(chrono_tz::Asia::Tokyo);
+//! let norm = NormalizeConfig::default();
+//! for it in client.history(date, SkDataType::PrepaidHistory, &norm, false)? {
+//!     println!("{} {} {}円", it.date, it.menu, it.price);
+//! }
+//! # Ok::<(), String>(())
+//! ```
+use crate::logging::redact_form;
+use crate::normalize::{normalize, NormalizeConfig};
 use chrono::{Date, Datelike, TimeZone};
 use chrono_tz::{Asia::Tokyo, Tz};
+use log::{debug, warn};
+use parsercher::dom::{Dom, DomType};
 
+/// login credentials of the mypage.
 #[derive(serde_derive::Deserialize, Debug)]
 pub struct SkUser {
     pub user: String,
     pub pass: String,
 }
 
+/// a row of the purchase or payment history.
 #[derive(Debug)]
 pub struct SkDatum {
     pub date: Date<Tz>,
     pub price: u32,
     pub shop: String,
+    /// the menu normalized for comparison
     pub menu: String,
+    /// the menu as downloaded. the sync marker is computed from it.
+    pub raw_menu: String,
+    /// how the payment was deposited. None for purchases.
+    pub deposit: Option<Deposit>,
+    /// the record this item was split from. None for whole records.
+    pub item_of: Option<ItemOf>,
 }
 
+/// where a split item came from.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ItemOf {
+    /// the sync marker of the whole record
+    pub marker: String,
+    /// the position of the item in the menu
+    pub index: usize,
+}
+
+/// how a payment was deposited.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Deposit {
+    /// 口座入金
+    Account,
+    /// カード入金(レジなどでの現金チャージ)
+    Card,
+}
+
+/// kind of the history.
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum SkDataType {
     /// 残高入金履歴
     PaymentHistory,
@@ -22,30 +70,147 @@ pub enum SkDataType {
     PrepaidHistory,
 }
 
-pub fn parse_sk_csv(year: i32, data: &str, dtype: SkDataType) -> Result<Vec<SkDatum>, String> {
-    match data.split_once("\r\n") {
-        None => {
-            return Err("split error".to_string());
+struct SkColumns {
+    date: usize,
+    shop: usize,
+    menu: Option<usize>,
+    /// amount columns. a payment row has a value in one of them.
+    prices: Vec<(usize, Option<Deposit>)>,
+}
+
+fn get_columns(headers: &csv::StringRecord, dtype: SkDataType) -> Result<SkColumns, String> {
+    let find = |name: &str| {
+        headers
+            .iter()
+            .position(|it| it.trim_start_matches('\u{feff}').trim() == name)
+    };
+    let require = |name: &str| find(name).ok_or(format!("column [{}] not found", name));
+
+    let prices = match dtype {
+        SkDataType::PaymentHistory => {
+            let prices: Vec<_> = [
+                ("口座入金額", Deposit::Account),
+                ("カード入金額", Deposit::Card),
+            ]
+            .iter()
+            .filter_map(|(name, deposit)| Some((find(name)?, Some(*deposit))))
+            .collect();
+            if prices.is_empty() {
+                return Err("column [口座入金額] or [カード入金額] not found".to_string());
+            }
+            prices
         }
-        Some((_, csv)) => {
-            let mut reader = csv::Reader::from_reader(csv.as_bytes());
-            let mut v = Vec::new();
-            for result in reader.records() {
-                let record = result.unwrap();
-                let datum = SkDatum {
-                    date: parse_sk_date(year, &record[0])?,
-                    shop: record[1].to_string(),
-                    menu: kana::combine(&kana::half2full(&record[2])),
-                    price: dparse(match dtype {
-                        SkDataType::PaymentHistory => &record[3],
-                        SkDataType::PrepaidHistory => &record[4],
-                    })?,
-                };
-                v.push(datum);
+        SkDataType::PrepaidHistory => vec![(require("購入金額")?, None)],
+    };
+
+    return Ok(SkColumns {
+        date: require("日付")?,
+        shop: require("利用店舗")?,
+        menu: find("利用品目"),
+        prices,
+    });
+}
+
+fn parse_sk_record(
+    year: i32,
+    record: &csv::StringRecord,
+    columns: &SkColumns,
+    norm: &NormalizeConfig,
+) -> Result<Vec<SkDatum>, String> {
+    let field = |i: usize| record.get(i).ok_or(format!("column {} missing", i + 1));
+    let date = parse_sk_date(year, field(columns.date)?)
+        .map_err(|e| format!("date [{}] {}", field(columns.date).unwrap_or(""), e))?;
+    let shop = field(columns.shop)?;
+    let raw_menu = match columns.menu {
+        Some(i) => field(i)?,
+        None => "",
+    };
+    let menu = normalize(norm, raw_menu);
+
+    let mut v = Vec::new();
+    for (i, deposit) in &columns.prices {
+        let price = field(*i)?.trim();
+        // the other deposit column of a payment is empty.
+        if deposit.is_some() && price == "" {
+            continue;
+        }
+        v.push(SkDatum {
+            date,
+            price: dparse(price)?,
+            shop: shop.to_string(),
+            menu: menu.clone(),
+            raw_menu: raw_menu.to_string(),
+            deposit: *deposit,
+            item_of: None,
+        });
+    }
+    if v.is_empty() {
+        return Err("deposit not found".to_string());
+    }
+    return Ok(v);
+}
+
+/// parses the history csv. columns are looked up by the header names.
+///
+/// broken rows are errors with the line number, or skipped with a warning when `lenient`.
+pub fn parse_sk_csv(
+    year: i32,
+    data: &str,
+    dtype: SkDataType,
+    norm: &NormalizeConfig,
+    lenient: bool,
+) -> Result<Vec<SkDatum>, String> {
+    // the first line is the period like "抽出期間：2022.7"
+    let (_, csv) = data.split_once("\n").ok_or("header not found")?;
+
+    let mut reader = csv::ReaderBuilder::new()
+        .flexible(true)
+        .from_reader(csv.as_bytes());
+    let headers = reader
+        .headers()
+        .map_err(|e| format!("csv header broken:{}", e))?
+        .clone();
+    let columns = get_columns(&headers, dtype)?;
+
+    let mut v = Vec::new();
+    for result in reader.records() {
+        let parsed = result
+            .map_err(|e| (e.position().map(|p| p.line()), e.to_string()))
+            .and_then(|record| {
+                parse_sk_record(year, &record, &columns, norm)
+                    .map_err(|e| (record.position().map(|p| p.line()), e))
+            });
+        match parsed {
+            Ok(data) => v.extend(data),
+            Err((line, e)) => {
+                // lines are counted in the csv part, after the period line.
+                let line = line.map_or("?".to_string(), |it| (it + 1).to_string());
+                let msg = format!("csv line {}: {}", line, e);
+                if !lenient {
+                    return Err(msg);
+                }
+                warn!("skipped {}", msg);
             }
-            return Ok(v);
         }
     }
+    return Ok(v);
+}
+
+/// splits the menu into items sorted for comparison. the menu is expected to be normalized.
+/// seikyo reorders items of the same purchase between downloads.
+pub fn menu_items(menu: &str) -> Vec<String> {
+    let mut items: Vec<String> = menu
+        .split("/")
+        .map(|it| it.trim().to_string())
+        .filter(|it| it != "")
+        .collect();
+    items.sort();
+    return items;
+}
+
+/// compares menus as multisets of items.
+pub fn same_menu(a: &str, b: &str) -> bool {
+    return a == b || menu_items(a) == menu_items(b);
 }
 
 fn dparse(d: &str) -> Result<u32, String> {
@@ -63,60 +228,161 @@ fn parse_sk_date(year: i32, data: &str) -> Result<Date<Tz>, String> {
     let month = dparse(month).map_err(|e| format!("month err:{}", e))?;
     let day = dparse(day).map_err(|e| format!("day err:{}", e))?;
 
-    let dt = Tokyo.ymd(year, month as u32, day as u32);
+    let dt = Tokyo
+        .ymd_opt(year, month as u32, day as u32)
+        .single()
+        .ok_or("no such date")?;
 
     return Ok(dt);
 }
 
-pub fn get_sk_agent(user: SkUser) -> Result<ureq::Agent, String> {
-    let agent = ureq::agent();
-    /*
-    let proxy = ureq::Proxy::new("localhost:8888").map_err(|e| e.to_string())?;
-    let tls = native_tls::TlsConnector::builder()
-        .danger_accept_invalid_certs(true)
-        .build()
-        .map_err(|e| e.to_string())?;
-    let agent = ureq::builder()
-        .redirects(20)
-        .tls_connector(std::sync::Arc::new(tls))
-        .proxy(proxy)
-        .build();
-    // */
-    let resp = agent
-        .post("https://mp.seikyou.jp/mypage-sp/Auth.login.do")
-        .send_form(&[("loginId", &user.user), ("password", &user.pass)])
-        .map_err(|e| format!("http err:{}", e))?;
-
-    if let None = resp.header("Set-Cookie") {
-        return Ok(agent);
-    }
-    return Err("login failure!".to_string());
-}
-
-pub fn get_sk_history(
-    agent: &ureq::Agent,
-    date: Date<Tz>,
-    dtype: SkDataType,
-) -> Result<Vec<SkDatum>, String> {
-    let resp = agent
-        .post(match dtype {
+/// a session of the seikyo mypage.
+#[derive(Debug)]
+pub struct SkClient {
+    agent: ureq::Agent,
+}
+
+impl SkClient {
+    /// logs in to the mypage.
+    pub fn login(user: SkUser) -> Result<SkClient, String> {
+        let agent = ureq::agent();
+        /*
+        let proxy = ureq::Proxy::new("localhost:8888").map_err(|e| e.to_string())?;
+        let tls = native_tls::TlsConnector::builder()
+            .danger_accept_invalid_certs(true)
+            .build()
+            .map_err(|e| e.to_string())?;
+        let agent = ureq::builder()
+            .redirects(20)
+            .tls_connector(std::sync::Arc::new(tls))
+            .proxy(proxy)
+            .build();
+        // */
+        let form = [
+            ("loginId", user.user.as_str()),
+            ("password", user.pass.as_str()),
+        ];
+        debug!(
+            "POST https://mp.seikyou.jp/mypage-sp/Auth.login.do {}",
+            redact_form(&form)
+        );
+        let resp = agent
+            .post("https://mp.seikyou.jp/mypage-sp/Auth.login.do")
+            .send_form(&form)
+            .map_err(|e| format!("http err:{}", e))?;
+
+        if let None = resp.header("Set-Cookie") {
+            return Ok(SkClient { agent });
+        }
+        return Err("login failure!".to_string());
+    }
+
+    /// fetches the history of the month of `date`.
+    pub fn history(
+        &self,
+        date: Date<Tz>,
+        dtype: SkDataType,
+        norm: &NormalizeConfig,
+        lenient: bool,
+    ) -> Result<Vec<SkDatum>, String> {
+        let url = match dtype {
             SkDataType::PaymentHistory => {
                 "https://mp.seikyou.jp/mypage-sp/PaymentHistory.csvDownload.do"
             }
             SkDataType::PrepaidHistory => {
                 "https://mp.seikyou.jp/mypage-sp/PrepaidHistory.csvDownload.do"
             }
-        })
-        .send_form(&[("rirekiDate", &date.format("%Y年%m月").to_string())])
-        .map_err(|err| format!("failure to get csv:{:?}", err))?;
-    if resp.status() != 200 {
-        return Err(format!("resp:{:?}", resp.into_string()));
-    };
+        };
+        let rireki_date = date.format("%Y年%m月").to_string();
+        debug!("POST {} rirekiDate={}", url, rireki_date);
+        let resp = self
+            .agent
+            .post(url)
+            .send_form(&[("rirekiDate", &rireki_date)])
+            .map_err(|err| format!("failure to get csv:{:?}", err))?;
+        if resp.status() != 200 {
+            return Err(format!("resp:{:?}", resp.into_string()));
+        };
 
-    let data = resp
-        .into_string()
-        .map_err(|e| format!("encode err:{:?}", e))?;
-    return parse_sk_csv(date.year(), &data, dtype);
+        let data = resp
+            .into_string()
+            .map_err(|e| format!("encode err:{:?}", e))?;
+        return parse_sk_csv(date.year(), &data, dtype, norm, lenient);
+    }
+
+    /// fetches the current prepaid balance.
+    pub fn balance(&self) -> Result<u32, String> {
+        debug!("GET https://mp.seikyou.jp/mypage-sp/Top.init.do");
+        let resp = self
+            .agent
+            .get("https://mp.seikyou.jp/mypage-sp/Top.init.do")
+            .call()
+            .map_err(|err| format!("failure to get top page:{:?}", err))?;
+
+        let html = resp
+            .into_string()
+            .map_err(|e| format!("encode err:{:?}", e))?;
+        return parse_sk_balance(&html);
+    }
+}
+
+fn parse_sk_balance(html: &str) -> Result<u32, String> {
+    // "<dt>プリペイド残高</dt><dd>1,234円</dd>"
+    let root_dom = parsercher::parse(html).map_err(|e| e.to_string())?;
+    let text = find_definition(&root_dom, "プリペイド残高").ok_or("balance not found")?;
+    let number = text
+        .trim()
+        .strip_suffix("円")
+        .ok_or(format!("balance unit not found [{}]", text.trim()))?;
+
+    return dparse(&number.trim().replace(",", ""));
+}
+
+/// text of the <dd> right after the <dt> of the term.
+fn find_definition(dom: &Dom, term: &str) -> Option<String> {
+    let children = dom.get_children()?;
+    for (i, child) in children.iter().enumerate() {
+        if is_tag(child, "dt") && get_all_text(child).trim() == term {
+            return children[i + 1..]
+                .iter()
+                .find(|it| it.dom_type == DomType::Tag)
+                .filter(|it| is_tag(it, "dd"))
+                .map(|it| get_all_text(it));
+        }
+        if let Some(text) = find_definition(child, term) {
+            return Some(text);
+        }
+    }
+    return None;
+}
+
+fn is_tag(dom: &Dom, name: &str) -> bool {
+    return dom.get_tag().map_or(false, |it| it.get_name() == name);
+}
+
+fn get_all_text(dom: &Dom) -> String {
+    let mut text = dom
+        .get_text()
+        .map(|it| it.get_text().to_string())
+        .unwrap_or_default();
+    for child in dom.get_children().into_iter().flatten() {
+        text.push_str(&get_all_text(child));
+    }
+    return text;
+}
+
+/// a record for tests of the modules using seikyo records.
+#[cfg(test)]
+pub(crate) fn make_datum(date: Date<Tz>, shop: &str, menu: &str, price: u32) -> SkDatum {
+    return SkDatum {
+        date,
+        price,
+        shop: shop.to_string(),
+        menu: menu.to_string(),
+        raw_menu: menu.to_string(),
+        deposit: None,
+        item_of: None,
+    };
 }
 
 #[cfg(test)]
@@ -131,6 +397,27 @@ mod tests {
         assert_eq!(data[0].date, Tokyo.ymd(2021, 6, 29));
         assert_eq!(data[0].shop, "京大ルネＤ");
         assert_eq!(data[0].price, 1000);
+        assert_eq!(data[0].deposit, Some(Deposit::Card));
+    }
+
+    #[test]
+    fn deposit_csv_test() {
+        let data = "抽出期間：2022.6
+日付,利用店舗,口座入金額,カード入金額
+\"6/25(土)\",\"口座振替\",\"5000\",\"\"
+\"6/29(水)\",\"京大ルネＤ\",\"\",\"1000\"
+\"6/30(木)\",\"京大ルネＤ\",\"\",\"\"
+";
+        let norm = NormalizeConfig::default();
+        let result = parse_sk_csv(2022, data, SkDataType::PaymentHistory, &norm, true).unwrap();
+        assert_eq!(result.len(), 2);
+        assert_eq!(result[0].price, 5000);
+        assert_eq!(result[0].deposit, Some(Deposit::Account));
+        assert_eq!(result[1].price, 1000);
+        assert_eq!(result[1].deposit, Some(Deposit::Card));
+
+        let result = parse_sk_csv(2022, data, SkDataType::PaymentHistory, &norm, false);
+        assert_eq!(result.unwrap_err(), "csv line 5: deposit not found");
     }
 
     #[test]
@@ -141,6 +428,16 @@ mod tests {
         assert_eq!(result, Err("error:InvalidDigit value:a1234".to_string()));
     }
 
+    #[test]
+    fn parse_sk_date_test() {
+        assert_eq!(parse_sk_date(2022, "7/1(金)"), Ok(Tokyo.ymd(2022, 7, 1)));
+        assert_eq!(
+            parse_sk_date(2022, "2/29(火)"),
+            Err("no such date".to_string())
+        );
+        assert!(parse_sk_date(2022, "13/1(日)").is_err());
+    }
+
     #[test]
     fn prepaid_csv_test() {
         let data = sk_load_file(2022, SkDataType::PrepaidHistory);
@@ -151,6 +448,48 @@ mod tests {
         assert_eq!(data[0].price, 473);
     }
 
+    #[test]
+    fn header_csv_test() {
+        let norm = NormalizeConfig::default();
+        // CRLF and reordered columns
+        let data = "抽出期間：2022.7\r\n利用品目,購入金額,日付,利用店舗\r\n\"唐揚げｶﾚｰM\",\"407\",\"7/19(火)\",\"京大ルネＤ\"\r\n";
+        let result = parse_sk_csv(2022, data, SkDataType::PrepaidHistory, &norm, false).unwrap();
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].date, Tokyo.ymd(2022, 7, 19));
+        assert_eq!(result[0].shop, "京大ルネＤ");
+        assert_eq!(result[0].menu, "唐揚げカレーM");
+        assert_eq!(result[0].price, 407);
+
+        let result = parse_sk_csv(2022, data, SkDataType::PaymentHistory, &norm, false);
+        assert_eq!(
+            result.unwrap_err(),
+            "column [口座入金額] or [カード入金額] not found"
+        );
+        assert!(parse_sk_csv(2022, "", SkDataType::PrepaidHistory, &norm, false).is_err());
+    }
+
+    #[test]
+    fn broken_csv_test() {
+        let norm = NormalizeConfig::default();
+        let data = "抽出期間：2022.7
+日付,利用店舗,利用品目,加算ポイント,購入金額
+\"7/19(火)\",\"京大ルネＤ\",\"味噌汁\",\"0.3\",\"33\"
+\"7/20(水)\",\"京大ルネＤ\",\"味噌汁\",\"0.3\",\"--\"
+\"7/21\",\"京大ルネＤ\",\"味噌汁\",\"0.3\",\"33\"
+\"7/22(金)\",\"京大ルネＤ\"
+\"7/25(月)\",\"京大ルネＤ\",\"味噌汁\",\"0.3\",\"33\"
+";
+        let result = parse_sk_csv(2022, data, SkDataType::PrepaidHistory, &norm, false);
+        assert_eq!(
+            result.unwrap_err(),
+            "csv line 4: error:InvalidDigit value:--"
+        );
+
+        let result = parse_sk_csv(2022, data, SkDataType::PrepaidHistory, &norm, true).unwrap();
+        let dates: Vec<_> = result.iter().map(|it| it.date.day()).collect();
+        assert_eq!(dates, vec![19, 25]);
+    }
+
     fn sk_load_file(year: i32, dtype: SkDataType) -> Vec<SkDatum> {
         let fname = match dtype {
             SkDataType::PaymentHistory => "./src/testdata/paymentHistory_20220724.csv",
@@ -160,6 +499,39 @@ mod tests {
         let mut contents = String::new();
         f.read_to_string(&mut contents)
             .expect("something went wrong reading the file");
-        return parse_sk_csv(year, &contents, dtype).unwrap();
+        return parse_sk_csv(year, &contents, dtype, &NormalizeConfig::default(), false).unwrap();
+    }
+
+    #[test]
+    fn parse_sk_balance_test() {
+        let html = r#"
+<dl class="balance">
+<dt>ポイント残高</dt>
+<dd>56ポイント</dd>
+<dt>プリペイド残高</dt>
+<dd><span class="num">1,234</span>円</dd>
+</dl>
+        "#;
+        assert_eq!(parse_sk_balance(html), Ok(1234));
+        assert!(parse_sk_balance("<p>プリペイド残高</p><p>1,234円</p>").is_err());
+        assert!(parse_sk_balance("<dl><dt>プリペイド残高</dt><dd>--円</dd></dl>").is_err());
+    }
+
+    #[test]
+    fn same_menu_test() {
+        assert!(same_menu(
+            "味噌汁/ほうれん草/ローストンカツごまソ",
+            "ローストンカツごまソ/味噌汁/ほうれん草"
+        ));
+        assert!(same_menu(
+            "唐揚げカレーM/ほうれん草",
+            "ほうれん草 / 唐揚げカレーM"
+        ));
+        assert!(same_menu("", ""));
+        assert!(!same_menu(
+            "味噌汁/味噌汁/ほうれん草",
+            "味噌汁/ほうれん草/ほうれん草"
+        ));
+        assert!(!same_menu("味噌汁/ほうれん草", "味噌汁"));
     }
 }
@@ -0,0 +1,214 @@
+use crate::items::menu_lines;
+use crate::sk::SkDatum;
+use chrono::{Date, Datelike, TimeZone};
+use chrono_tz::Tz;
+use std::collections::{BTreeMap, HashMap};
+
+const WEEKDAYS: [&str; 7] = ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"];
+
+/// parses "2022-04" into the first day of the month.
+pub fn parse_month(tz: Tz, data: &str) -> Result<Date<Tz>, String> {
+    let (year, month) = data
+        .split_once("-")
+        .ok_or(format!("month [{}] must be YYYY-MM", data))?;
+    let year = year
+        .parse::<i32>()
+        .map_err(|e| format!("month [{}] year err:{}", data, e))?;
+    let month = month
+        .parse::<u32>()
+        .map_err(|e| format!("month [{}] month err:{}", data, e))?;
+    return tz
+        .ymd_opt(year, month, 1)
+        .single()
+        .ok_or(format!("month [{}] out of range", data));
+}
+
+/// first days of the months from `from` to `to` inclusive.
+pub fn months_between(from: Date<Tz>, to: Date<Tz>) -> Vec<Date<Tz>> {
+    let tz = from.timezone();
+    let mut months = Vec::new();
+    let (mut year, mut month) = (from.year(), from.month());
+    while (year, month) <= (to.year(), to.month()) {
+        months.push(tz.ymd(year, month, 1));
+        if month == 12 {
+            year = year + 1;
+            month = 1;
+        } else {
+            month = month + 1;
+        }
+    }
+    return months;
+}
+
+#[derive(Default, Debug, PartialEq)]
+pub struct Total {
+    pub records: u32,
+    pub amount: i64,
+}
+
+impl Total {
+    fn add(&mut self, price: u32) {
+        self.records += 1;
+        self.amount += price as i64;
+    }
+
+    fn average(&self) -> i64 {
+        if self.records == 0 {
+            return 0;
+        }
+        return self.amount / self.records as i64;
+    }
+}
+
+/// spending of prepaid records and top-ups of payment records.
+#[derive(Default, Debug)]
+pub struct Stats {
+    pub spending: Total,
+    pub topup: Total,
+    pub months: BTreeMap<String, Total>,
+    pub weekdays: [Total; 7],
+    pub shops: HashMap<String, Total>,
+    /// number of times each item was bought
+    pub items: HashMap<String, u32>,
+}
+
+pub fn get_stats(prepaid: &[SkDatum], payment: &[SkDatum]) -> Stats {
+    let mut stats = Stats::default();
+    for it in prepaid {
+        stats.spending.add(it.price);
+        stats
+            .months
+            .entry(it.date.format("%Y/%m").to_string())
+            .or_default()
+            .add(it.price);
+        stats.weekdays[it.date.weekday().num_days_from_monday() as usize].add(it.price);
+        stats
+            .shops
+            .entry(it.shop.clone())
+            .or_default()
+            .add(it.price);
+        for item in menu_lines(&it.menu) {
+            *stats.items.entry(item).or_default() += 1;
+        }
+    }
+    for it in payment {
+        stats.topup.add(it.price);
+    }
+    return stats;
+}
+
+pub fn format_stats(stats: &Stats, top: usize) -> String {
+    let mut text = format!(
+        "spending: {}円 ({} meals, average {}円)\n",
+        stats.spending.amount,
+        stats.spending.records,
+        stats.spending.average()
+    );
+    text.push_str(&format!(
+        "top-ups: {}円 ({} times, top-ups - spending {}円)\n",
+        stats.topup.amount,
+        stats.topup.records,
+        stats.topup.amount - stats.spending.amount
+    ));
+
+    text.push_str("\nby month:\n");
+    for (month, it) in &stats.months {
+        push_total(&mut text, month, it);
+    }
+
+    text.push_str("\nby weekday:\n");
+    for (weekday, it) in WEEKDAYS.iter().zip(stats.weekdays.iter()) {
+        push_total(&mut text, weekday, it);
+    }
+
+    text.push_str("\nby shop:\n");
+    let mut shops: Vec<_> = stats.shops.iter().collect();
+    shops.sort_by(|a, b| b.1.amount.cmp(&a.1.amount).then(a.0.cmp(b.0)));
+    for (shop, it) in shops {
+        push_total(&mut text, shop, it);
+    }
+
+    text.push_str("\ntop items:\n");
+    let mut items: Vec<_> = stats.items.iter().collect();
+    items.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+    for (item, count) in items.into_iter().take(top) {
+        text.push_str(&format!("  {} {}\n", item, count));
+    }
+
+    return text;
+}
+
+fn push_total(text: &mut String, label: &str, total: &Total) {
+    text.push_str(&format!(
+        "  {} {} meals {}円 (average {}円)\n",
+        label,
+        total.records,
+        total.amount,
+        total.average()
+    ));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sk::make_datum;
+    use chrono_tz::Asia::Tokyo;
+
+    #[test]
+    fn months_between_test() {
+        let from = parse_month(Tokyo, "2022-11").unwrap();
+        let to = parse_month(Tokyo, "2023-02").unwrap();
+        let months: Vec<_> = months_between(from, to)
+            .iter()
+            .map(|it| it.format("%Y/%m").to_string())
+            .collect();
+        assert_eq!(months, vec!["2022/11", "2022/12", "2023/01", "2023/02"]);
+
+        assert!(months_between(to, from).is_empty());
+        assert!(parse_month(Tokyo, "2022/04").is_err());
+        assert!(parse_month(Tokyo, "2022-13").is_err());
+    }
+
+    #[test]
+    fn stats_test() {
+        let prepaid = vec![
+            make_datum(
+                Tokyo.ymd(2022, 7, 4),
+                "第一食堂",
+                "チキンカツカレーM/ほうれん草",
+                473,
+            ), // Mon
+            make_datum(Tokyo.ymd(2022, 7, 5), "第一食堂", "チキンカツカレーM", 407),
+            make_datum(Tokyo.ymd(2022, 7, 5), "購買", "お茶", 130),
+            make_datum(
+                Tokyo.ymd(2022, 8, 1),
+                "第一食堂",
+                "唐揚げ丼/ほうれん草",
+                561,
+            ), // Mon
+        ];
+        let payment = vec![make_datum(Tokyo.ymd(2022, 7, 1), "", "入金", 1000)];
+        let stats = get_stats(&prepaid, &payment);
+
+        assert_eq!(
+            stats.spending,
+            Total {
+                records: 4,
+                amount: 1571
+            }
+        );
+        assert_eq!(stats.spending.average(), 392);
+        assert_eq!(stats.topup.amount, 1000);
+        assert_eq!(stats.months["2022/07"].amount, 1010);
+        assert_eq!(stats.weekdays[0].records, 2);
+        assert_eq!(stats.weekdays[1].amount, 537);
+        assert_eq!(stats.shops["第一食堂"].amount, 1441);
+        assert_eq!(stats.items["ほうれん草"], 2);
+
+        let text = format_stats(&stats, 2);
+        assert!(text.starts_with("spending: 1571円 (4 meals, average 392円)\n"));
+        assert!(text.contains("top-ups: 1000円 (1 times, top-ups - spending -571円)\n"));
+        assert!(text.contains("\nby shop:\n  第一食堂 3 meals 1441円 (average 480円)\n  購買 "));
+        assert!(text.ends_with("\ntop items:\n  ほうれん草 2\n  チキンカツカレーM 2\n"));
+    }
+}
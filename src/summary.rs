@@ -0,0 +1,110 @@
+use chrono::Date;
+use chrono_tz::Tz;
+
+/// totals of records sent to one category in one month.
+#[derive(serde_derive::Serialize, Debug, Clone)]
+pub struct CategorySummary {
+    pub month: String,
+    pub large_category: String,
+    pub middle_category: String,
+    pub records: u32,
+    pub amount: i64,
+}
+
+/// result of one run. it is reported to notification targets.
+#[derive(serde_derive::Serialize, Debug, Default)]
+pub struct RunSummary {
+    pub sent: Vec<CategorySummary>,
+    pub balance_delta: Option<i64>,
+    pub errors: Vec<String>,
+}
+
+impl RunSummary {
+    pub fn add_sent(
+        &mut self,
+        month: Date<Tz>,
+        large_category: &str,
+        middle_category: &str,
+        amount: i64,
+    ) {
+        let month = month.format("%Y/%m").to_string();
+        for it in self.sent.iter_mut() {
+            if it.month == month
+                && it.large_category == large_category
+                && it.middle_category == middle_category
+            {
+                it.records += 1;
+                it.amount += amount;
+                return;
+            }
+        }
+        self.sent.push(CategorySummary {
+            month,
+            large_category: large_category.to_string(),
+            middle_category: middle_category.to_string(),
+            records: 1,
+            amount,
+        });
+    }
+
+    pub fn is_success(&self) -> bool {
+        self.errors.is_empty()
+    }
+
+    pub fn records(&self) -> u32 {
+        self.sent.iter().map(|it| it.records).sum()
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut text = format!(
+            "skmf: {} ({} records sent)\n",
+            if self.is_success() {
+                "success"
+            } else {
+                "failure"
+            },
+            self.records()
+        );
+        for it in &self.sent {
+            text.push_str(&format!(
+                "{} {}/{}: {} records {}円\n",
+                it.month, it.large_category, it.middle_category, it.records, it.amount
+            ));
+        }
+        if let Some(delta) = self.balance_delta {
+            text.push_str(&format!("balance delta: {}円\n", delta));
+        }
+        for it in &self.errors {
+            text.push_str(&format!("Error:{}\n", it));
+        }
+        return text;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use chrono_tz::Asia::Tokyo;
+
+    #[test]
+    fn add_sent_test() {
+        let mut summary = RunSummary::default();
+        summary.add_sent(Tokyo.ymd(2022, 7, 1), "食費", "外食", 473);
+        summary.add_sent(Tokyo.ymd(2022, 7, 1), "食費", "外食", 407);
+        summary.add_sent(Tokyo.ymd(2022, 7, 1), "現金", "チャージ", 1000);
+        summary.add_sent(Tokyo.ymd(2022, 6, 1), "食費", "外食", 561);
+
+        assert_eq!(summary.sent.len(), 3);
+        assert_eq!(summary.sent[0].records, 2);
+        assert_eq!(summary.sent[0].amount, 880);
+        assert_eq!(summary.records(), 4);
+        assert!(summary.is_success());
+
+        summary.errors.push("login failure!".to_string());
+        let text = summary.to_text();
+        assert!(text.starts_with("skmf: failure (4 records sent)\n"));
+        assert!(text.contains("2022/07 食費/外食: 2 records 880円\n"));
+        assert!(text.ends_with("Error:login failure!\n"));
+    }
+}
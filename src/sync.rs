@@ -0,0 +1,475 @@
+use crate::config::{BalanceConfig, Config, SkMfConfig};
+use crate::{check, content, diff, items, journal, mf, report, shops, sk, summary};
+use chrono::{Date, Datelike, TimeZone, Utc};
+use chrono_tz::{Asia::Tokyo, Tz};
+use log::{debug, info, warn};
+use std::time::Instant;
+
+/// syncs the history of this and the last month, then reconciles the balance.
+/// with `create_missing`, the account and middle categories in the config are created first.
+pub fn run(
+    conf: Config,
+    create_missing: bool,
+    summary: &mut summary::RunSummary,
+    report: &mut report::RunReport,
+) -> Result<(), String> {
+    let mut journal = journal::load_journal(&conf.skmf.journal)?;
+    let items = items::load_items(conf.items.as_ref())?;
+
+    let started = Instant::now();
+    let mut mfs = mf::MfSession::login(conf.mf)?;
+    let ska = sk::SkClient::login(conf.sk)?;
+    report.login_seconds = started.elapsed().as_secs_f64();
+
+    if create_missing {
+        let created =
+            check::create_missing(&conf.skmf, &conf.shops, conf.balance.as_ref(), &mut mfs);
+        match created {
+            Ok(created) => {
+                for it in created {
+                    info!("created {}", it);
+                }
+            }
+            Err(e) => {
+                mfs.save()?;
+                return Err(e);
+            }
+        }
+    }
+
+    // a wrong name would be found after some records of the month were sent.
+    let problems = check::check_config(&conf.skmf, &conf.shops, conf.balance.as_ref(), &mfs);
+    if !problems.is_empty() {
+        mfs.save()?;
+        return Err(format!("config check failed: {}", problems.join(", ")));
+    }
+
+    let date = get_date(Tokyo);
+    info!("start(1) at {}", date);
+    if let Err(e) = send_skmf(
+        &mfs,
+        &ska,
+        date,
+        &conf.skmf,
+        &items,
+        &conf.shops,
+        &mut journal,
+        summary,
+        report,
+    ) {
+        mfs.save()?;
+        return Err(e);
+    }
+
+    let date = get_past_date(date);
+    info!("start(2) at {}", date);
+    if let Err(e) = send_skmf(
+        &mfs,
+        &ska,
+        date,
+        &conf.skmf,
+        &items,
+        &conf.shops,
+        &mut journal,
+        summary,
+        report,
+    ) {
+        mfs.save()?;
+        return Err(e);
+    }
+
+    journal.prune(date);
+    journal::save_journal(&journal)?;
+
+    if let Some(balance) = &conf.balance {
+        if let Err(e) =
+            reconcile_balance(&mut mfs, &ska, &conf.skmf, balance, &mut journal, summary)
+        {
+            mfs.save()?;
+            return Err(e);
+        }
+    }
+
+    mfs.save()?;
+    Ok(())
+}
+
+/// the first day of this month.
+pub fn get_date(tz: Tz) -> Date<Tz> {
+    let utcdate = Utc::today().naive_utc();
+    let date = tz.from_utc_date(&utcdate);
+
+    tz.ymd(date.year(), date.month(), 1)
+}
+
+/// today.
+pub fn get_today(tz: Tz) -> Date<Tz> {
+    let utcdate = Utc::today().naive_utc();
+    tz.from_utc_date(&utcdate)
+}
+
+/// the first day of the previous month.
+pub fn get_past_date(dt: Date<Tz>) -> Date<Tz> {
+    let mut year = dt.year();
+    let mut month = dt.month();
+    let tz = dt.timezone();
+
+    if month <= 1 {
+        month = 12;
+        year = year - 1;
+    } else {
+        month = month - 1;
+    }
+
+    tz.ymd(year, month, 1)
+}
+
+fn send_skmf(
+    mfs: &mf::MfSession,
+    ska: &sk::SkClient,
+    date: Date<Tz>,
+    skmf: &SkMfConfig,
+    items: &items::Items,
+    shops: &shops::ShopTable,
+    journal: &mut journal::Journal,
+    summary: &mut summary::RunSummary,
+    report: &mut report::RunReport,
+) -> Result<(), String> {
+    let started = Instant::now();
+    let mfd = mfs.history(&skmf.mf_subaccount, date, &skmf.normalize)?;
+    journal.settle_adjustments(&mfd);
+    let prepaid = ska.history(
+        date,
+        sk::SkDataType::PrepaidHistory,
+        &skmf.normalize,
+        skmf.lenient_csv,
+    )?;
+    let payment = ska.history(
+        date,
+        sk::SkDataType::PaymentHistory,
+        &skmf.normalize,
+        skmf.lenient_csv,
+    )?;
+    report.fetch_seconds += started.elapsed().as_secs_f64();
+
+    let month = report.month(date);
+    let prepaid_content = |it: &sk::SkDatum| {
+        content::render_content(&skmf.prepaid_content, shops, &skmf.normalize, it)
+    };
+    let payment_content = |it: &sk::SkDatum| {
+        content::render_content(&skmf.payment_content, shops, &skmf.normalize, it)
+    };
+
+    let prepaid = plan_records(
+        &mfd,
+        prepaid,
+        sk::SkDataType::PrepaidHistory,
+        &skmf.matching,
+        &prepaid_content,
+        &|it| items.expand(it),
+        date,
+        journal,
+        month,
+    );
+    let payment = plan_records(
+        &mfd,
+        payment,
+        sk::SkDataType::PaymentHistory,
+        &skmf.matching,
+        &payment_content,
+        &|it| it,
+        date,
+        journal,
+        month,
+    );
+    journal::save_journal(journal)?;
+
+    let mut i = 0;
+
+    for it in prepaid {
+        let shop = shops::get_shop(
+            shops,
+            &it.shop,
+            &skmf.normalize,
+            &skmf.mf_large_category,
+            &skmf.mf_middle_category,
+        );
+        let memo = get_memo(skmf, &shop, items.memo(&it.menu));
+        let memo = with_marker(memo, diff::sync_marker(sk::SkDataType::PrepaidHistory, &it));
+        let content = prepaid_content(&it);
+        let datum = mf::MfAssetDatum {
+            is_transfer: false,
+            is_income: false,
+            sub_account_from: String::new(),
+            sub_account_to: String::new(),
+            updated_at: it.date,
+            amount: it.price as i32,
+            sub_account: skmf.mf_subaccount.clone(),
+            content,
+            memo,
+            large_category: shop.large_category.to_string(),
+            middle_category: shop.middle_category.to_string(),
+        };
+        let entry = journal.begin(sk::SkDataType::PrepaidHistory, &it)?;
+        if let Err(e) = mfs.send(&datum) {
+            mark_failed(month, sk::SkDataType::PrepaidHistory, &it, &e);
+            return Err(e);
+        }
+        journal.commit(entry)?;
+        month.mark(
+            sk::SkDataType::PrepaidHistory,
+            &it,
+            report::RecordStatus::Pending,
+            report::RecordStatus::Sent,
+        );
+        summary.add_sent(
+            date,
+            shop.large_category,
+            shop.middle_category,
+            it.price as i64,
+        );
+        i = i + 1;
+    }
+    info!("prepaid. send {} records", i);
+    i = 0;
+
+    for it in payment {
+        let shop = shops::get_charge_shop(
+            shops,
+            &it.shop,
+            &skmf.normalize,
+            &skmf.mf_charge_large_category,
+            &skmf.mf_charge_middle_category,
+        );
+        let memo = get_memo(skmf, &shop, String::new());
+        let memo = with_marker(memo, diff::sync_marker(sk::SkDataType::PaymentHistory, &it));
+        let content = payment_content(&it);
+        let datum = mf::MfAssetDatum {
+            is_transfer: true,
+            is_income: false,
+            sub_account_from: get_subaccount_from(skmf, it.deposit).to_string(),
+            sub_account_to: skmf.mf_subaccount.clone(),
+            updated_at: it.date,
+            amount: it.price as i32,
+            sub_account: skmf.mf_subaccount.clone(),
+            content,
+            memo,
+            large_category: shop.large_category.to_string(),
+            middle_category: shop.middle_category.to_string(),
+        };
+        let entry = journal.begin(sk::SkDataType::PaymentHistory, &it)?;
+        if let Err(e) = mfs.send(&datum) {
+            mark_failed(month, sk::SkDataType::PaymentHistory, &it, &e);
+            return Err(e);
+        }
+        journal.commit(entry)?;
+        month.mark(
+            sk::SkDataType::PaymentHistory,
+            &it,
+            report::RecordStatus::Pending,
+            report::RecordStatus::Sent,
+        );
+        summary.add_sent(
+            date,
+            shop.large_category,
+            shop.middle_category,
+            it.price as i64,
+        );
+        i = i + 1;
+    }
+    info!("payment. send {} records", i);
+
+    Ok(())
+}
+
+/// source subaccount of the payment by the deposit kind.
+fn get_subaccount_from(skmf: &SkMfConfig, deposit: Option<sk::Deposit>) -> &str {
+    match (deposit, &skmf.mf_account_subaccount_from) {
+        (Some(sk::Deposit::Account), Some(from)) => from,
+        _ => &skmf.mf_subaccount_from,
+    }
+}
+
+/// puts the shop label before the item list when memo_shop is set.
+fn get_memo(skmf: &SkMfConfig, shop: &shops::Shop, items: String) -> String {
+    if !skmf.memo_shop {
+        return items;
+    }
+    if items == "" {
+        return shop.label();
+    }
+    return format!("{}\n{}", shop.label(), items);
+}
+
+/// appends the sync marker as the last line of the memo.
+fn with_marker(memo: String, marker: String) -> String {
+    if memo == "" {
+        return marker;
+    }
+    return format!("{}\n{}", memo, marker);
+}
+
+/// returns records to send. records already in Moneyforward or in the journal are excluded.
+/// records not registered as a whole are split by `split` and matched per item.
+fn plan_records(
+    mfdata: &Vec<mf::MfDatum>,
+    skdata: Vec<sk::SkDatum>,
+    skdtype: sk::SkDataType,
+    matching: &diff::MatchConfig,
+    render: &dyn Fn(&sk::SkDatum) -> String,
+    split: &dyn Fn(Vec<sk::SkDatum>) -> Vec<sk::SkDatum>,
+    date: Date<Tz>,
+    journal: &mut journal::Journal,
+    month: &mut report::MonthReport,
+) -> Vec<sk::SkDatum> {
+    let diff = diff::get_split_diff(mfdata, skdata, skdtype, matching, render, split);
+    for it in &diff.records {
+        if let (Some(c), Some(reason)) = (it.matched, &it.reason) {
+            debug!(
+                "{} {}円 [{}] already synced as id:{} ({})",
+                it.datum.date.format("%Y/%m/%d"),
+                it.datum.price,
+                it.content,
+                c.id,
+                reason
+            );
+        }
+        month.add(
+            skdtype,
+            &it.datum,
+            it.matched.map(|c| &c.id),
+            it.reason.as_ref(),
+            it.similar.map(|c| &c.id),
+        );
+        if let Some(c) = it.similar {
+            warn!(
+                "{} {}円 [{}] differs from registered [{}] in content",
+                it.datum.date.format("%Y/%m/%d"),
+                it.datum.price,
+                it.content,
+                c.content
+            );
+        }
+    }
+    for c in &diff.orphans {
+        warn!(
+            "{} {}円 [{}] id:{} has no seikyo record",
+            c.date.format("%Y/%m/%d"),
+            c.price,
+            c.content,
+            c.id
+        );
+        month.add_orphan(skdtype, c);
+    }
+    let missing = diff.missing();
+
+    // records sent by previous runs may not be indexed by Moneyforward yet.
+    let records = journal.reconcile(skdtype, date, missing);
+    for it in &records {
+        month.mark(
+            skdtype,
+            it,
+            report::RecordStatus::Missing,
+            report::RecordStatus::Pending,
+        );
+    }
+    month.mark_journaled(skdtype);
+
+    return records;
+}
+
+fn mark_failed(
+    month: &mut report::MonthReport,
+    skdtype: sk::SkDataType,
+    it: &sk::SkDatum,
+    e: &str,
+) {
+    if let Some(record) = month.mark(
+        skdtype,
+        it,
+        report::RecordStatus::Pending,
+        report::RecordStatus::Failed,
+    ) {
+        record.error = Some(e.to_string());
+    }
+}
+
+fn reconcile_balance(
+    mfs: &mut mf::MfSession,
+    ska: &sk::SkClient,
+    skmf: &SkMfConfig,
+    conf: &BalanceConfig,
+    journal: &mut journal::Journal,
+    summary: &mut summary::RunSummary,
+) -> Result<(), String> {
+    let sk_balance = ska.balance()? as i64;
+    mfs.refresh_balances()?;
+    let mf_balance = mfs.balance(&skmf.mf_subaccount)?;
+
+    let delta = sk_balance - mf_balance;
+    summary.balance_delta = Some(delta);
+    if delta == 0 {
+        info!("balance matched:{}", sk_balance);
+        return Ok(());
+    }
+    warn!(
+        "balance mismatch! seikyo:{} mf:{} delta:{}",
+        sk_balance, mf_balance, delta
+    );
+
+    if !conf.adjust {
+        return Ok(());
+    }
+    // the balance of Moneyforward is stale until it indexes the entries sent.
+    if summary.records() > 0 || !journal.is_settled() {
+        warn!("balance not adjusted: waiting for Moneyforward to index the entries sent");
+        return Ok(());
+    }
+
+    let large_category = conf
+        .large_category
+        .as_ref()
+        .ok_or("balance.large_category is required to adjust")?;
+    let middle_category = conf
+        .middle_category
+        .as_ref()
+        .ok_or("balance.middle_category is required to adjust")?;
+
+    let today = get_today(Tokyo);
+    let marker = diff::balance_marker(today, delta);
+    let datum = mf::MfAssetDatum {
+        is_transfer: false,
+        is_income: delta > 0,
+        sub_account_from: String::new(),
+        sub_account_to: String::new(),
+        updated_at: today,
+        amount: delta.abs() as i32,
+        sub_account: skmf.mf_subaccount.clone(),
+        content: conf.content.clone(),
+        memo: marker.clone(),
+        large_category: large_category.clone(),
+        middle_category: middle_category.clone(),
+    };
+    let entry = journal.begin_adjustment(today, delta, &marker)?;
+    mfs.send(&datum)?;
+    journal.commit(entry)?;
+    info!("balance adjusted:{}", delta);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn get_past_date_test() {
+        let today = Tokyo.ymd(2020, 3, 1);
+        let result = get_past_date(today);
+        assert_eq!(result, Tokyo.ymd(2020, 2, 1));
+
+        let today = Tokyo.ymd(2020, 1, 1);
+        let result = get_past_date(today);
+        assert_eq!(result, Tokyo.ymd(2019, 12, 1));
+    }
+}